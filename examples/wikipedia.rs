@@ -10,38 +10,27 @@
 // to start a new browser when you create a session. The advantage of
 // the first method is it can run with all your existing user cookies,
 // the second one creates a completely new profile.
-#![feature(generators, use_extern_macros, proc_macro_non_items, nll)]
-#![recursion_limit="128"]
-extern crate tokio;
-extern crate futures_await as futures;
-extern crate rusty_driver;
-
-use tokio::run;
-use futures::prelude::await;
-use futures::prelude::*;
-use rusty_driver::{Locator, Driver, error::*};
+use rusty_driver::{error::*, Driver, Locator};
 use std::{env::args, process::exit};
 
-#[async]
-fn wikipedia(article: String) -> Result<()> {
-    let driver = await!(Driver::new("http://localhost:4444".into(), None))?;
-    await!(driver.clone().goto("https://www.wikipedia.org".into()))?;
-    let search = await!(
-        driver.clone().find(Locator::Css("form#search-form".into()), None)
-    )?;
-    await!(driver.clone().set_by_name(search.clone(), "search".into(), article))?;
-    await!(driver.clone().submit(search))?;
-    println!("{}", await!(driver.source())?);
+async fn wikipedia(article: String) -> Result<()> {
+    let driver = Driver::new("http://localhost:4444", None).await?;
+    driver.goto("https://www.wikipedia.org").await?;
+    let search = driver.find(Locator::Css("form#search-form".into()), None).await?;
+    driver.set_by_name(search.clone(), "search".into(), article).await?;
+    driver.submit(search).await?;
+    println!("{}", driver.source().await?);
     Ok(())
 }
 
-fn main() {
+#[tokio::main]
+async fn main() {
     if let Some(article) = args().nth(1) {
-        run(async_block! {
-            await!(wikipedia(article)).map_err(|e| eprintln!("error: {}", e))
-        })
+        if let Err(e) = wikipedia(article).await {
+            eprintln!("error: {}", e)
+        }
     } else {
-        eprintln!("usage curl: <url>");
+        eprintln!("usage wikipedia: <article>");
         exit(1)
     }
 }
@@ -0,0 +1,204 @@
+//! Accessibility audits using [axe-core].
+//!
+//! The axe-core script is embedded in the crate, it is injected into
+//! the page the first time an audit is run, so audits work without
+//! network access and regardless of the page's content security
+//! policy for remote scripts.
+//!
+//! ```text
+//! let violations = a11y::audit(&driver, None, &AuditOptions::default()).await?;
+//! a11y::check(&violations, Impact::Serious)?;
+//! ```
+//!
+//! [axe-core]: https://github.com/dequelabs/axe-core
+
+use crate::{error::*, Driver};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use webdriver::common::WebElement;
+
+/// The version of axe-core embedded in this crate.
+pub const AXE_VERSION: &str = "4.10.2";
+
+static AXE_SOURCE: &str = include_str!("../vendor/axe-core/axe.min.js");
+
+/// Whether the real axe-core script is embedded, rather than the
+/// placeholder that is there until `vendor/axe-core/update.sh` is run.
+fn is_vendored() -> bool {
+    AXE_SOURCE.contains("axe.run")
+}
+
+/// How severe a violation is, in increasing order.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Impact {
+    Minor,
+    Moderate,
+    Serious,
+    Critical,
+}
+
+impl fmt::Display for Impact {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Impact::Minor => "minor",
+            Impact::Moderate => "moderate",
+            Impact::Serious => "serious",
+            Impact::Critical => "critical",
+        };
+        f.write_str(s)
+    }
+}
+
+/// An element that failed an axe rule.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Node {
+    /// The outer HTML of the element (possibly truncated)
+    pub html: String,
+    /// Selectors identifying the element, nested arrays select
+    /// through iframes and shadow roots.
+    pub target: Vec<Value>,
+    pub impact: Option<Impact>,
+    /// Human readable summary of how to fix the problem
+    #[serde(default)]
+    pub failure_summary: Option<String>,
+}
+
+/// A violated axe rule, and the elements that violate it.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Violation {
+    /// The rule id, e.g. "color-contrast"
+    pub id: String,
+    pub impact: Option<Impact>,
+    pub description: String,
+    pub help: String,
+    pub help_url: String,
+    /// e.g. "wcag2aa", "best-practice"
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub nodes: Vec<Node>,
+}
+
+/// Options controlling which rules an audit runs.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct AuditOptions {
+    /// Only run rules with at least one of these tags,
+    /// e.g. `["wcag2a", "wcag2aa"]`. Empty means all rules.
+    pub tags: Vec<String>,
+    /// Rule ids to skip
+    pub disabled_rules: Vec<String>,
+}
+
+impl AuditOptions {
+    fn to_json(&self) -> Value {
+        let mut o = serde_json::Map::new();
+        if !self.tags.is_empty() {
+            o.insert(
+                "runOnly".into(),
+                serde_json::json!({"type": "tag", "values": self.tags}),
+            );
+        }
+        if !self.disabled_rules.is_empty() {
+            let rules = self
+                .disabled_rules
+                .iter()
+                .map(|r| (r.clone(), serde_json::json!({"enabled": false})))
+                .collect::<serde_json::Map<_, _>>();
+            o.insert("rules".into(), Value::Object(rules));
+        }
+        Value::Object(o)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AxeResult {
+    Ok { violations: Vec<Violation> },
+    Err { error: String },
+}
+
+async fn inject(driver: &Driver) -> Result<()> {
+    if !is_vendored() {
+        bail!(
+            "axe-core {} is not vendored in this build, run vendor/axe-core/update.sh",
+            AXE_VERSION
+        );
+    }
+    let present = driver
+        .execute("return typeof axe !== 'undefined'".to_string(), vec![])
+        .await?;
+    if present != Value::Bool(true) {
+        driver.execute(AXE_SOURCE.to_string(), vec![]).await?;
+    }
+    Ok(())
+}
+
+/// Run an accessibility audit of the current page, or if `root` is
+/// specified only of `root` and its descendants, and return the
+/// violations found.
+pub async fn audit(
+    driver: &Driver,
+    root: Option<WebElement>,
+    options: &AuditOptions,
+) -> Result<Vec<Violation>> {
+    inject(driver).await?;
+    let js = r#"
+        var done = arguments[arguments.length - 1];
+        var ctx = arguments[0] || document;
+        axe.run(ctx, arguments[1]).then(
+            function(r) { done({violations: r.violations}); },
+            function(e) { done({error: String(e)}); });
+    "#;
+    let root = match root {
+        None => Value::Null,
+        Some(elt) => serde_json::to_value(elt)?,
+    };
+    let res = driver
+        .execute_async(js.to_string(), vec![root, options.to_json()])
+        .await?;
+    match serde_json::from_value(res)? {
+        AxeResult::Ok { violations } => Ok(violations),
+        AxeResult::Err { error } => bail!("axe-core failed: {}", error),
+    }
+}
+
+/// Fail with `ErrorKind::AccessibilityViolations` if any of the
+/// `violations` has an impact of at least `min`. Violations without
+/// an impact are treated as `Minor`.
+pub fn check(violations: &[Violation], min: Impact) -> Result<()> {
+    let failed = violations
+        .iter()
+        .filter(|v| v.impact.unwrap_or(Impact::Minor) >= min)
+        .collect::<Vec<_>>();
+    if failed.is_empty() {
+        return Ok(());
+    }
+    let mut summary = String::new();
+    for v in &failed {
+        let impact = v.impact.unwrap_or(Impact::Minor);
+        summary.push_str(&format!(
+            "\n  [{}] {}: {} ({} nodes) {}",
+            impact,
+            v.id,
+            v.help,
+            v.nodes.len(),
+            v.help_url
+        ));
+    }
+    bail!(ErrorKind::AccessibilityViolations(failed.len(), summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs the real axe.min.js, run vendor/axe-core/update.sh"]
+    fn axe_is_embedded() {
+        assert!(is_vendored());
+        assert!(AXE_SOURCE.contains(AXE_VERSION));
+    }
+}
@@ -0,0 +1,367 @@
+//! A synchronous API, for programs that don't otherwise use async.
+//!
+//! `blocking::Driver` mirrors `crate::Driver`, each method blocks the
+//! calling thread until the command completes. It owns a single
+//! threaded tokio runtime that drives the underlying async driver, so
+//! it must not be used from inside another tokio runtime.
+//!
+//! ```text
+//! let driver = blocking::Driver::new("http://localhost:4444", None)?;
+//! driver.goto("https://example.com")?;
+//! let h1 = driver.find(Locator::Css("h1".into()), None)?;
+//! println!("{}", driver.text(h1)?);
+//! driver.quit()?;
+//! ```
+//!
+//! Functions that take an async `Driver`, such as `a11y::audit`, can
+//! be run with `block_on` and `as_async`.
+
+use crate::{
+    cassette::Recorder,
+    download::{self, Download},
+    error::*,
+    extract::{Extract, Link, Resource},
+    fetch::{FetchOptions, FetchResponse},
+    form::{self, FormValues},
+    table::Table,
+    transport::Transport,
+    Locator, NavigationTiming, PrintOptions, ReadyState, ServerStatus,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{future::Future, path::Path, sync::Arc, time::Duration};
+use tokio::runtime::{self, Runtime};
+use webdriver::{capabilities::Capabilities, common::WebElement};
+
+// Generate a method that blocks on the async method of the same name
+macro_rules! blocking {
+    ($($name:ident(&self $(, $arg:ident: $typ:ty)*) -> $ret:ty;)*) => {
+        $(
+            #[doc = concat!("See `crate::Driver::", stringify!($name), "`")]
+            pub fn $name(&self $(, $arg: $typ)*) -> Result<$ret> {
+                self.block_on(self.as_async().$name($($arg),*))
+            }
+        )*
+    };
+}
+
+/// A blocking handle to a webdriver session.
+///
+/// Like `crate::Driver` it is cheap to clone, and clones may be used
+/// from different threads. The session is closed when the last handle
+/// is dropped, or explicitly with `quit`.
+#[derive(Clone)]
+pub struct Driver {
+    driver: Option<crate::Driver>,
+    rt: Arc<Runtime>,
+}
+
+impl Drop for Driver {
+    fn drop(&mut self) {
+        if let Some(driver) = self.driver.take() {
+            let _ = self.rt.block_on(driver.quit());
+        }
+    }
+}
+
+impl Driver {
+    fn connect<F, Fut>(f: F) -> Result<Self>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<crate::Driver>>,
+    {
+        let rt = runtime::Builder::new_current_thread().enable_all().build()?;
+        let driver = rt.block_on(f())?;
+        Ok(Driver {
+            driver: Some(driver),
+            rt: Arc::new(rt),
+        })
+    }
+
+    /// Create a new webdriver session on the specified server
+    pub fn new(webdriver_url: &str, user_agent: Option<String>) -> Result<Self> {
+        Self::connect(|| crate::Driver::new(webdriver_url, user_agent))
+    }
+
+    /// Create a new webdriver session on the specified server, with
+    /// additional capabilities
+    pub fn new_with_capabilities(
+        webdriver_url: &str,
+        user_agent: Option<String>,
+        capabilities: Capabilities,
+    ) -> Result<Self> {
+        Self::connect(|| {
+            crate::Driver::new_with_capabilities(webdriver_url, user_agent, capabilities)
+        })
+    }
+
+    /// See `crate::Driver::new_with_transport`. The transport must
+    /// work on a tokio runtime.
+    pub fn new_with_transport(
+        webdriver_url: &str,
+        user_agent: Option<String>,
+        capabilities: Capabilities,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self> {
+        Self::connect(|| {
+            crate::Driver::new_with_transport(
+                webdriver_url,
+                user_agent,
+                capabilities,
+                transport,
+            )
+        })
+    }
+
+    /// See `crate::Driver::new_with_recorder`. The transport must work
+    /// on a tokio runtime.
+    pub fn new_with_recorder(
+        webdriver_url: &str,
+        user_agent: Option<String>,
+        capabilities: Capabilities,
+        transport: Arc<dyn Transport>,
+        recorder: Recorder,
+    ) -> Result<Self> {
+        Self::connect(|| {
+            crate::Driver::new_with_recorder(
+                webdriver_url,
+                user_agent,
+                capabilities,
+                transport,
+                recorder,
+            )
+        })
+    }
+
+    /// The async driver this handle wraps
+    pub fn as_async(&self) -> &crate::Driver {
+        self.driver.as_ref().unwrap()
+    }
+
+    /// Run `future` to completion on this driver's runtime
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.rt.block_on(future)
+    }
+
+    /// Close the session, waiting for the server to respond. If other
+    /// clones of this handle exist only this handle is dropped.
+    pub fn quit(mut self) -> Result<()> {
+        let driver = self.driver.take().unwrap();
+        self.rt.block_on(driver.quit())
+    }
+
+    blocking! {
+        goto(&self, url: &str) -> ();
+        goto_and_wait(&self, url: &str, state: ReadyState) -> NavigationTiming;
+        wait_for_ready_state(&self, state: ReadyState) -> ();
+        current_url(&self) -> url::Url;
+        source(&self) -> String;
+        back(&self) -> ();
+        forward(&self) -> ();
+        title(&self) -> String;
+        refresh(&self) -> ();
+        switch_to_frame(&self, frame: WebElement) -> ();
+        switch_to_parent_frame(&self) -> ();
+        switch_to_window(&self, window: String) -> ();
+        window_handle(&self) -> String;
+        window_handles(&self) -> Vec<String>;
+        close_window(&self) -> ();
+        delete_all_cookies(&self) -> ();
+        status(&self) -> ServerStatus;
+        execute(&self, script: String, args: Vec<Value>) -> Value;
+        execute_async(&self, script: String, args: Vec<Value>) -> Value;
+        print_pdf(&self, options: PrintOptions) -> Vec<u8>;
+        wait_for_navigation(&self, current: Option<url::Url>) -> ();
+        find(&self, locator: Locator, root: Option<WebElement>) -> WebElement;
+        find_all(&self, locator: Locator, root: Option<WebElement>) -> Vec<WebElement>;
+        wait_for_find(&self, locator: Locator, root: Option<WebElement>) -> WebElement;
+        wait_for_find_all(
+            &self,
+            locator: Locator,
+            root: Option<WebElement>
+        ) -> Vec<WebElement>;
+        active_element(&self) -> WebElement;
+        focus(&self, eid: WebElement) -> ();
+        press_keys(&self, keys: &str) -> ();
+        tab_order(&self, max: usize) -> Vec<WebElement>;
+        attr(&self, eid: WebElement, attribute: String) -> Option<String>;
+        prop(&self, eid: WebElement, prop: String) -> Option<String>;
+        text(&self, eid: WebElement) -> String;
+        html(&self, eid: WebElement, inner: bool) -> String;
+        computed_role(&self, eid: WebElement) -> String;
+        computed_label(&self, eid: WebElement) -> String;
+        find_all_by_role(
+            &self,
+            role: &str,
+            name: Option<&str>,
+            root: Option<WebElement>
+        ) -> Vec<WebElement>;
+        find_by_role(
+            &self,
+            role: &str,
+            name: Option<&str>,
+            root: Option<WebElement>
+        ) -> WebElement;
+        click(&self, eid: WebElement) -> ();
+        scroll_into_view(&self, eid: WebElement) -> ();
+        follow(&self, eid: WebElement) -> ();
+        set_by_name(&self, eid: WebElement, name: String, value: String) -> ();
+        submit(&self, eid: WebElement) -> ();
+        submit_with(&self, eid: WebElement, button: Locator) -> ();
+        submit_using(&self, eid: WebElement, button_label: String) -> ();
+        submit_direct(&self, eid: WebElement) -> ();
+        submit_sneaky(&self, eid: WebElement, field: String, value: String) -> ();
+        links(&self) -> Vec<Link>;
+        resources(&self) -> Vec<Resource>;
+        fetch(&self, url: &str, options: FetchOptions) -> FetchResponse;
+        form_values(&self, form: WebElement, submitter: Option<WebElement>) -> FormValues;
+        upload_file(&self, eid: WebElement, path: &Path) -> ();
+        upload_files(&self, eid: WebElement, paths: &[&Path]) -> ();
+        table(&self, eid: WebElement) -> Table;
+    }
+
+    /// See `crate::Driver::execute_typed`
+    pub fn execute_typed<A, R>(&self, script: String, args: A) -> Result<R>
+    where
+        A: Serialize,
+        R: DeserializeOwned,
+    {
+        self.block_on(self.as_async().execute_typed(script, args))
+    }
+
+    /// See `crate::Driver::extract`
+    pub fn extract<T: DeserializeOwned>(
+        &self,
+        locator: Locator,
+        root: Option<WebElement>,
+        spec: &Extract,
+    ) -> Result<Vec<T>> {
+        self.block_on(self.as_async().extract(locator, root, spec))
+    }
+
+    /// See `crate::Driver::fill_form`
+    pub fn fill_form<T: Serialize + ?Sized>(
+        &self,
+        form: WebElement,
+        values: &T,
+    ) -> Result<()> {
+        self.block_on(self.as_async().fill_form(form, values))
+    }
+
+    /// See `crate::Driver::shadow_root`
+    pub fn shadow_root(&self, eid: WebElement) -> Result<ShadowRoot<'_>> {
+        let root = self.block_on(self.as_async().shadow_root(eid))?;
+        Ok(ShadowRoot { driver: self, root })
+    }
+
+    /// A handle to the select element `elt`, see `form::Select`
+    pub fn select(&self, elt: WebElement) -> Select<'_> {
+        Select {
+            driver: self,
+            select: form::Select::new(self.as_async(), elt),
+        }
+    }
+
+    /// Run `action`, which should trigger a download, and wait up to
+    /// `timeout` for the downloaded file to appear in `dir`. See
+    /// `crate::Driver::expect_download`.
+    pub fn expect_download<F>(
+        &self,
+        dir: &Path,
+        timeout: Duration,
+        action: F,
+    ) -> Result<Download>
+    where
+        F: FnOnce() -> Result<()>,
+    {
+        let before = download::snapshot(dir)?;
+        action()?;
+        self.block_on(download::wait_for_download(self.as_async(), dir, &before, timeout))
+    }
+}
+
+/// A blocking handle to a shadow root, see `crate::ShadowRoot`.
+#[derive(Clone)]
+pub struct ShadowRoot<'a> {
+    driver: &'a Driver,
+    root: crate::ShadowRoot<'a>,
+}
+
+impl<'a> ShadowRoot<'a> {
+    /// The webdriver id of this shadow root
+    pub fn id(&self) -> &str {
+        self.root.id()
+    }
+
+    /// See `crate::ShadowRoot::find`
+    pub fn find(&self, locator: Locator) -> Result<WebElement> {
+        self.driver.block_on(self.root.find(locator))
+    }
+
+    /// See `crate::ShadowRoot::find_all`
+    pub fn find_all(&self, locator: Locator) -> Result<Vec<WebElement>> {
+        self.driver.block_on(self.root.find_all(locator))
+    }
+}
+
+impl<'a> Serialize for ShadowRoot<'a> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.root.serialize(serializer)
+    }
+}
+
+/// A blocking handle to a select element, see `form::Select`.
+pub struct Select<'a> {
+    driver: &'a Driver,
+    select: form::Select<'a>,
+}
+
+impl<'a> Select<'a> {
+    /// The select element
+    pub fn element(&self) -> &WebElement {
+        self.select.element()
+    }
+
+    /// See `form::Select::is_multiple`
+    pub fn is_multiple(&self) -> Result<bool> {
+        self.driver.block_on(self.select.is_multiple())
+    }
+
+    /// See `form::Select::options`
+    pub fn options(&self) -> Result<Vec<WebElement>> {
+        self.driver.block_on(self.select.options())
+    }
+
+    /// See `form::Select::selected`
+    pub fn selected(&self) -> Result<Vec<WebElement>> {
+        self.driver.block_on(self.select.selected())
+    }
+
+    /// See `form::Select::select_by_value`
+    pub fn select_by_value(&self, value: &str) -> Result<()> {
+        self.driver.block_on(self.select.select_by_value(value))
+    }
+
+    /// See `form::Select::select_by_visible_text`
+    pub fn select_by_visible_text(&self, text: &str) -> Result<()> {
+        self.driver.block_on(self.select.select_by_visible_text(text))
+    }
+
+    /// See `form::Select::select_by_index`
+    pub fn select_by_index(&self, index: usize) -> Result<()> {
+        self.driver.block_on(self.select.select_by_index(index))
+    }
+
+    /// See `form::Select::deselect_by_value`
+    pub fn deselect_by_value(&self, value: &str) -> Result<()> {
+        self.driver.block_on(self.select.deselect_by_value(value))
+    }
+
+    /// See `form::Select::deselect_all`
+    pub fn deselect_all(&self) -> Result<()> {
+        self.driver.block_on(self.select.deselect_all())
+    }
+}
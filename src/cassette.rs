@@ -0,0 +1,417 @@
+//! Recording and replaying the traffic with the webdriver server.
+//!
+//! A `Recorder` passed to `Driver::new_with_recorder` writes every
+//! exchange with the server to a cassette, one JSON `Entry` per line:
+//! the command, the request body, and the response status and body.
+//! Each line is flushed as it is written, so the cassette of a run
+//! that crashed or hung is complete up to that point. A failure to
+//! write the cassette doesn't fail the command being recorded, it
+//! stops the recording, and is reported by `Recorder::check`.
+//!
+//! A `Replay` is a `Transport` that answers requests from a cassette
+//! instead of a browser, reproducing exactly what the browser said
+//! during the recorded run.
+//!
+//! ```text
+//! let recorder = Recorder::create("run.jsonl")?.redact(password);
+//! let transport = transport::default_transport()?;
+//! let driver =
+//!     Driver::new_with_recorder(url, None, caps, transport, recorder.clone()).await?;
+//! ...
+//! recorder.check()?;
+//! let replay = Arc::new(Replay::open("run.jsonl", Matching::Strict)?);
+//! let driver = Driver::new_with_transport(url, None, caps, replay).await?;
+//! ```
+//!
+//! Cookie values, values of headers, keys, and URL query parameters
+//! that look like credentials (e.g. `Authorization`, `access_token`),
+//! and any string passed to `redact` are replaced with `[scrubbed]`
+//! before they are written. Requests are scrubbed the same way before
+//! they are matched against the cassette, a `Replay` must be given the
+//! same `redact` strings as the `Recorder`.
+
+use crate::{
+    error::*,
+    transport::{Request, Response, Transport},
+};
+use futures::future::{self, BoxFuture};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const SCRUBBED: &str = "[scrubbed]";
+
+/// One exchange with the webdriver server, a line of a cassette.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// The name of the webdriver command, e.g. `FindElement`
+    pub command: String,
+    pub method: String,
+    /// The path of the request url, so a cassette can be replayed
+    /// against a server at another address
+    pub path: String,
+    /// The request body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Value>,
+    /// The response status, `None` if the request failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// The response body, a string if it isn't JSON
+    #[serde(default)]
+    pub response: Value,
+    /// Why the request failed, e.g. the connection was refused
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn is_sensitive(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    matches!(
+        name.as_str(),
+        "authorization"
+            | "proxy-authorization"
+            | "cookie"
+            | "set-cookie"
+            | "apikey"
+            | "api_key"
+            | "x-api-key"
+            | "sig"
+            | "signature"
+    ) || ["token", "secret", "password"].iter().any(|s| name.contains(s))
+}
+
+/// Replaces credentials in the recorded traffic.
+#[derive(Clone, Default, Debug)]
+struct Scrubber {
+    secrets: Vec<String>,
+}
+
+impl Scrubber {
+    fn add(&mut self, secret: String) {
+        // an empty string would match between every character
+        if !secret.is_empty() {
+            self.secrets.push(secret)
+        }
+    }
+
+    fn string(&self, s: &mut String) {
+        for secret in &self.secrets {
+            if s.contains(secret.as_str()) {
+                *s = s.replace(secret.as_str(), SCRUBBED);
+            }
+        }
+        if let Ok(mut url) = url::Url::parse(s) {
+            if !url.query_pairs().any(|(k, _)| is_sensitive(&k)) {
+                return;
+            }
+            let pairs = url
+                .query_pairs()
+                .map(|(k, v)| {
+                    let v = if is_sensitive(&k) { SCRUBBED.into() } else { v };
+                    (k.into_owned(), v.into_owned())
+                })
+                .collect::<Vec<_>>();
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+            *s = url.into();
+        }
+    }
+
+    fn value(&self, v: &mut Value) {
+        match v {
+            Value::String(s) => self.string(s),
+            Value::Array(a) => {
+                // a header, e.g. in `fetch`
+                if let [Value::String(k), Value::String(v)] = a.as_mut_slice() {
+                    if is_sensitive(k) {
+                        *v = SCRUBBED.into();
+                    }
+                }
+                a.iter_mut().for_each(|v| self.value(v))
+            }
+            Value::Object(o) => {
+                let is_cookie = o.contains_key("name")
+                    && ["domain", "path", "secure", "httpOnly"]
+                        .iter()
+                        .any(|k| o.contains_key(*k));
+                for (k, v) in o.iter_mut() {
+                    if v.is_string() && (is_sensitive(k) || is_cookie && k == "value") {
+                        *v = SCRUBBED.into();
+                    } else {
+                        self.value(v)
+                    }
+                }
+            }
+            Value::Null | Value::Bool(_) | Value::Number(_) => (),
+        }
+    }
+
+    /// The scrubbed path and body of `req`
+    fn request(&self, req: &Request) -> (String, Option<Value>) {
+        let mut path = req.url.path().to_string();
+        self.string(&mut path);
+        let mut body = req.body.as_ref().map(|b| {
+            serde_json::from_str(b).unwrap_or_else(|_| Value::String(b.clone()))
+        });
+        if let Some(ref mut body) = body {
+            self.value(body);
+        }
+        (path, body)
+    }
+}
+
+struct Output {
+    out: Box<dyn Write + Send>,
+    /// The first error writing `out`, after which nothing more is
+    /// written
+    failed: Option<String>,
+}
+
+/// Writes the traffic of a session to a cassette, see the module
+/// documentation. Clones write to the same cassette.
+#[derive(Clone)]
+pub struct Recorder {
+    out: Arc<Mutex<Output>>,
+    scrubber: Scrubber,
+}
+
+impl Recorder {
+    /// Record to `out`
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        Recorder {
+            out: Arc::new(Mutex::new(Output {
+                out: Box::new(out),
+                failed: None,
+            })),
+            scrubber: Scrubber::default(),
+        }
+    }
+
+    /// Record to the file at `path`, replacing it if it exists
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(File::create(path)?))
+    }
+
+    /// Replace `secret` wherever it appears in the cassette, e.g. a
+    /// password typed into a form. An empty `secret` is ignored.
+    pub fn redact<S: Into<String>>(mut self, secret: S) -> Self {
+        self.scrubber.add(secret.into());
+        self
+    }
+
+    /// Fail if writing the cassette failed, with the first error.
+    /// The cassette holds the exchanges before the one that failed.
+    pub fn check(&self) -> Result<()> {
+        match self.out.lock().unwrap().failed {
+            None => Ok(()),
+            Some(ref e) => bail!("writing the cassette failed: {}", e),
+        }
+    }
+
+    /// Append the exchange of `command`, `req` and `res` to the
+    /// cassette, unless writing it already failed
+    pub(crate) fn record(&self, command: &str, req: &Request, res: &Result<Response>) {
+        let mut out = self.out.lock().unwrap();
+        if out.failed.is_none() {
+            let line = self.line(command, req, res);
+            let written = line.and_then(|line| {
+                out.out.write_all(&line)?;
+                Ok(out.out.flush()?)
+            });
+            if let Err(e) = written {
+                out.failed = Some(e.to_string())
+            }
+        }
+    }
+
+    /// The scrubbed cassette line of an exchange
+    fn line(
+        &self,
+        command: &str,
+        req: &Request,
+        res: &Result<Response>,
+    ) -> Result<Vec<u8>> {
+        let scrubber = &self.scrubber;
+        let (path, request) = scrubber.request(req);
+        let mut entry = Entry {
+            command: command.to_string(),
+            method: req.method.to_string(),
+            path,
+            request,
+            status: None,
+            content_type: None,
+            response: Value::Null,
+            error: None,
+        };
+        match res {
+            Ok(res) => {
+                entry.status = Some(res.status.as_u16());
+                entry.content_type = res.header("content-type").map(String::from);
+                entry.response = serde_json::from_slice(&res.body).unwrap_or_else(|_| {
+                    Value::String(String::from_utf8_lossy(&res.body).into_owned())
+                });
+                scrubber.value(&mut entry.response);
+            }
+            Err(e) => {
+                let mut e = e.to_string();
+                scrubber.string(&mut e);
+                entry.error = Some(e);
+            }
+        }
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        Ok(line)
+    }
+}
+
+/// How a `Replay` finds the entry for a request.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Matching {
+    /// Requests must be made in the recorded order
+    Strict,
+    /// Each request is answered by the first unused entry with the
+    /// same method, path and body, so independent requests may be
+    /// reordered. Repeated requests, e.g. while polling, are still
+    /// answered in the recorded order.
+    Request,
+}
+
+struct ReplayState {
+    entries: Vec<Entry>,
+    used: Vec<bool>,
+}
+
+/// A `Transport` answering requests from a cassette, see the module
+/// documentation.
+pub struct Replay {
+    state: Mutex<ReplayState>,
+    matching: Matching,
+    scrubber: Scrubber,
+}
+
+impl Replay {
+    /// Replay `entries`
+    pub fn new(entries: Vec<Entry>, matching: Matching) -> Self {
+        Replay {
+            state: Mutex::new(ReplayState {
+                used: vec![false; entries.len()],
+                entries,
+            }),
+            matching,
+            scrubber: Scrubber::default(),
+        }
+    }
+
+    /// Replay the cassette at `path`
+    pub fn open<P: AsRef<Path>>(path: P, matching: Matching) -> Result<Self> {
+        let mut entries = vec![];
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Self::new(entries, matching))
+    }
+
+    /// Scrub `secret` from requests before matching them, as the
+    /// `Recorder` did. An empty `secret` is ignored.
+    pub fn redact<S: Into<String>>(mut self, secret: S) -> Self {
+        self.scrubber.add(secret.into());
+        self
+    }
+
+    /// The entries that haven't been replayed yet
+    pub fn remaining(&self) -> Vec<Entry> {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .iter()
+            .zip(&state.used)
+            .filter(|(_, used)| !**used)
+            .map(|(e, _)| e.clone())
+            .collect()
+    }
+
+    fn reply(&self, req: &Request) -> Result<Response> {
+        let (path, body) = self.scrubber.request(req);
+        let method = req.method.to_string();
+        let matches =
+            |e: &Entry| e.method == method && e.path == path && e.request == body;
+        let describe = || match body {
+            None => format!("{} {}", method, path),
+            Some(ref b) => format!("{} {} {}", method, path, b),
+        };
+        let mut state = self.state.lock().unwrap();
+        let i = match self.matching {
+            Matching::Strict => match state.used.iter().position(|used| !used) {
+                None => bail!(ErrorKind::CassetteMismatch(describe())),
+                Some(i) if !matches(&state.entries[i]) => {
+                    let e = &state.entries[i];
+                    bail!(ErrorKind::CassetteMismatch(format!(
+                        "{}, expected {} {} (entry {})",
+                        describe(),
+                        e.method,
+                        e.path,
+                        i + 1
+                    )))
+                }
+                Some(i) => i,
+            },
+            Matching::Request => {
+                let found = state
+                    .entries
+                    .iter()
+                    .zip(&state.used)
+                    .position(|(e, used)| !used && matches(e));
+                match found {
+                    None => bail!(ErrorKind::CassetteMismatch(describe())),
+                    Some(i) => i,
+                }
+            }
+        };
+        state.used[i] = true;
+        let entry = &state.entries[i];
+        let status = match (entry.status, &entry.error) {
+            (_, Some(e)) => bail!("{}", e),
+            (None, None) => bail!("cassette entry {} has no status", i + 1),
+            (Some(s), None) => StatusCode::from_u16(s).map_err(http::Error::from)?,
+        };
+        let body = match (&entry.content_type, &entry.response) {
+            (Some(ctype), v) if ctype.starts_with("application/json") => {
+                serde_json::to_vec(v)?
+            }
+            (_, Value::String(s)) => s.clone().into_bytes(),
+            (_, v) => serde_json::to_vec(v)?,
+        };
+        let headers = entry
+            .content_type
+            .iter()
+            .map(|c| ("content-type".to_string(), c.clone()))
+            .collect();
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+impl Transport for Replay {
+    fn send(&self, request: Request) -> BoxFuture<'static, Result<Response>> {
+        Box::pin(future::ready(self.reply(&request)))
+    }
+
+    /// Replays don't wait, the recorded responses are already there
+    fn sleep(&self, _duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(future::ready(()))
+    }
+}
@@ -0,0 +1,688 @@
+//! A browser driven web crawler.
+//!
+//! The crawler keeps a frontier of URLs to visit, navigates the
+//! browser to each in turn, and hands the loaded page to a `Handler`
+//! which extracts items and returns the links to follow. Links are
+//! normalized, de-duplicated, and filtered by scope, depth, and
+//! `robots.txt` before being queued, and requests to the same host are
+//! spaced out by a politeness delay.
+//!
+//! A page that fails, e.g. because it didn't load or the handler
+//! returned an error, is retried later, up to `max_retries` times.
+//! Errors after which the crawl can't go on, e.g. the session was
+//! closed or the checkpoint can't be written, stop the crawl.
+//!
+//! The crawl state can be checkpointed to a file after every page, so
+//! a crawl that crashed can be resumed where it left off, starting
+//! with the page it was on.
+//!
+//! ```text
+//! struct Titles;
+//!
+//! impl Handler for Titles {
+//!     type Item = String;
+//!
+//!     async fn handle(&mut self, driver: &Driver, _page: &Page) -> Result<Visit<String>> {
+//!         let links = driver.links().await?.into_iter().map(|l| l.url).collect();
+//!         Ok(Visit { items: vec![driver.title().await?], links })
+//!     }
+//! }
+//!
+//! let mut crawler = Crawler::new(CrawlConfig::default(), &["https://example.com/"])?;
+//! let titles = crawler.run(&driver, &mut Titles).await?;
+//! ```
+
+use crate::{
+    error::*,
+    fetch::{Credentials, FetchOptions},
+    Driver,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use url::Url;
+use webdriver::error::ErrorStatus;
+
+/// Which discovered links the crawler may follow, relative to the
+/// seed URLs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Scope {
+    /// Same scheme, host, and port as one of the seeds
+    SameOrigin,
+    /// The host of one of the seeds, or any of its subdomains
+    /// (ignoring a leading "www.")
+    SameDomain,
+    /// Any http or https URL
+    Any,
+}
+
+/// Crawler settings.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CrawlConfig {
+    pub scope: Scope,
+    /// The maximum link depth from a seed, seeds are depth 0
+    pub max_depth: usize,
+    /// Stop after visiting this many pages
+    pub max_pages: Option<usize>,
+    /// The minimum time between two requests to the same host. A
+    /// larger `Crawl-delay` in robots.txt takes precedence.
+    pub politeness: Duration,
+    /// Fetch and obey robots.txt
+    pub respect_robots: bool,
+    /// The user agent matched against robots.txt groups, by its
+    /// product token, e.g. `rusty-driver` in `rusty-driver/0.3`
+    pub robots_agent: String,
+    /// How many times a failed page is retried before it is given up
+    /// on, see `Crawler::failed`
+    pub max_retries: usize,
+    /// Save the crawl state to this file after every page
+    pub checkpoint: Option<PathBuf>,
+}
+
+impl Default for CrawlConfig {
+    fn default() -> Self {
+        CrawlConfig {
+            scope: Scope::SameOrigin,
+            max_depth: 3,
+            max_pages: None,
+            politeness: Duration::from_secs(1),
+            respect_robots: true,
+            robots_agent: "rusty-driver".into(),
+            max_retries: 2,
+            checkpoint: None,
+        }
+    }
+}
+
+/// A page the crawler navigated to.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Page {
+    /// The (normalized) URL from the frontier
+    pub url: Url,
+    /// The URL the browser ended up at, after any redirects
+    pub final_url: Url,
+    pub depth: usize,
+}
+
+/// The result of handling a page: the items extracted from it, and
+/// the links found on it that the crawler should consider following.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Visit<T> {
+    pub items: Vec<T>,
+    pub links: Vec<Url>,
+}
+
+/// Per page processing supplied by the user of the crawler.
+pub trait Handler {
+    type Item;
+
+    /// Called with the browser on `page` once it has loaded.
+    fn handle<'a>(
+        &'a mut self,
+        driver: &'a Driver,
+        page: &'a Page,
+    ) -> impl Future<Output = Result<Visit<Self::Item>>> + 'a;
+}
+
+/// Normalize `url` for de-duplication: drop the fragment and an
+/// empty query. The url crate already lower cases the scheme and
+/// host, and removes default ports.
+pub fn normalize(url: &Url) -> Url {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    if url.query() == Some("") {
+        url.set_query(None);
+    }
+    url
+}
+
+/// The rules of a robots.txt that apply to one user agent.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Robots {
+    /// (allow, path pattern)
+    rules: Vec<(bool, String)>,
+    pub crawl_delay: Option<Duration>,
+}
+
+/// The product token of a user agent, lower cased, e.g. `rusty-driver`
+/// for `Rusty-Driver/0.3 (+https://example.com/bot)`.
+fn product_token(agent: &str) -> String {
+    agent
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic() || *c == '-' || *c == '_')
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+impl Robots {
+    /// Parse `txt`, keeping the group that best matches `agent`: the
+    /// groups naming the product token of `agent` if any, otherwise
+    /// the `*` groups. As in RFC 9309 the product tokens are compared
+    /// case-insensitively.
+    pub fn parse(txt: &str, agent: &str) -> Self {
+        let agent = product_token(agent);
+        let mut specific = Robots::default();
+        let mut generic = Robots::default();
+        let mut found_specific = false;
+        // the agents of the current group, and whether its rules started
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        for line in txt.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let (key, value) = match line.split_once(':') {
+                Some((k, v)) => (k.trim().to_lowercase(), v.trim()),
+                None => continue,
+            };
+            match key.as_str() {
+                "user-agent" => {
+                    if in_rules {
+                        agents.clear();
+                        in_rules = false;
+                    }
+                    agents.push(value.to_string());
+                    continue;
+                }
+                "allow" | "disallow" | "crawl-delay" => in_rules = true,
+                // e.g. sitemap, which doesn't belong to a group
+                _ => continue,
+            }
+            let is_specific =
+                !agent.is_empty() && agents.iter().any(|a| product_token(a) == agent);
+            let is_generic = agents.iter().any(|a| a == "*");
+            let target = if is_specific {
+                found_specific = true;
+                &mut specific
+            } else if is_generic {
+                &mut generic
+            } else {
+                continue;
+            };
+            match key.as_str() {
+                "allow" if !value.is_empty() => target.rules.push((true, value.into())),
+                "disallow" if !value.is_empty() => target.rules.push((false, value.into())),
+                "crawl-delay" => {
+                    if let Ok(d) = value.parse::<f64>() {
+                        target.crawl_delay = Some(Duration::from_secs_f64(d.max(0.)));
+                    }
+                }
+                _ => (),
+            }
+        }
+        if found_specific {
+            specific
+        } else {
+            generic
+        }
+    }
+
+    /// Does `pattern`, which may contain `*` wildcards and end with a
+    /// `$` anchor, match `path`. Unanchored patterns match prefixes.
+    fn matches(pattern: &str, path: &str) -> bool {
+        let pattern = match pattern.strip_suffix('$') {
+            Some(p) => p.to_string(),
+            None => format!("{}*", pattern),
+        };
+        let (p, s) = (pattern.as_bytes(), path.as_bytes());
+        let (mut pi, mut si) = (0, 0);
+        // the position of the last star, and where in s it resumed
+        let mut star: Option<(usize, usize)> = None;
+        while si < s.len() {
+            if pi < p.len() && p[pi] == b'*' {
+                star = Some((pi, si));
+                pi += 1;
+            } else if pi < p.len() && p[pi] == s[si] {
+                pi += 1;
+                si += 1;
+            } else if let Some((sp, ss)) = star {
+                pi = sp + 1;
+                si = ss + 1;
+                star = Some((sp, ss + 1));
+            } else {
+                return false;
+            }
+        }
+        p[pi..].iter().all(|c| *c == b'*')
+    }
+
+    /// May the path (and query) of `url` be crawled. The longest
+    /// matching rule wins, allow wins a tie.
+    pub fn allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            None => url.path().to_string(),
+            Some(q) => format!("{}?{}", url.path(), q),
+        };
+        let mut best: Option<(usize, bool)> = None;
+        for (allow, pattern) in &self.rules {
+            if Self::matches(pattern, &path) {
+                let better = match best {
+                    None => true,
+                    Some((len, a)) => pattern.len() > len || pattern.len() == len && *allow && !a,
+                };
+                if better {
+                    best = Some((pattern.len(), *allow));
+                }
+            }
+        }
+        best.map(|(_, allow)| allow).unwrap_or(true)
+    }
+}
+
+/// The part of the crawl that is saved in a checkpoint.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+struct State {
+    seeds: Vec<String>,
+    frontier: VecDeque<(String, usize)>,
+    seen: HashSet<String>,
+    visited: usize,
+    /// The number of times each URL that is to be retried failed
+    #[serde(default)]
+    attempts: HashMap<String, usize>,
+    /// The URLs given up on, with the last error
+    #[serde(default)]
+    failed: Vec<(String, String)>,
+}
+
+/// Is `e` an error after which the crawl can't continue, rather than a
+/// failure of one page: the session or its window is gone, the
+/// webdriver server can't be reached, or reading or writing a file,
+/// e.g. the checkpoint, failed.
+fn is_fatal(e: &Error) -> bool {
+    match e.kind() {
+        ErrorKind::IoErr(_) | ErrorKind::HttpError(_) => true,
+        #[cfg(feature = "hyper")]
+        ErrorKind::HyperError(_) => true,
+        ErrorKind::WebDriver(e) => matches!(
+            e.error,
+            ErrorStatus::InvalidSessionId
+                | ErrorStatus::SessionNotCreated
+                | ErrorStatus::NoSuchWindow
+        ),
+        _ => false,
+    }
+}
+
+/// A crawl in progress.
+pub struct Crawler {
+    config: CrawlConfig,
+    seeds: Vec<Url>,
+    state: State,
+    robots: HashMap<String, Robots>,
+    last_request: HashMap<String, Instant>,
+}
+
+impl Crawler {
+    /// Start a new crawl from `seeds`.
+    pub fn new(config: CrawlConfig, seeds: &[&str]) -> Result<Self> {
+        let seeds = seeds
+            .iter()
+            .map(|s| Ok(normalize(&Url::parse(s)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut state = State::default();
+        for s in &seeds {
+            if state.seen.insert(s.to_string()) {
+                state.frontier.push_back((s.to_string(), 0));
+            }
+        }
+        state.seeds = seeds.iter().map(|s| s.to_string()).collect();
+        Ok(Crawler {
+            config,
+            seeds,
+            state,
+            robots: HashMap::new(),
+            last_request: HashMap::new(),
+        })
+    }
+
+    /// Resume the crawl checkpointed in `config.checkpoint` if the
+    /// file exists, otherwise start a new crawl from `seeds`.
+    pub fn resume(config: CrawlConfig, seeds: &[&str]) -> Result<Self> {
+        let path = match config.checkpoint {
+            Some(ref p) if p.exists() => p.clone(),
+            _ => return Self::new(config, seeds),
+        };
+        let state: State = serde_json::from_slice(&fs::read(&path)?)?;
+        let seeds = state
+            .seeds
+            .iter()
+            .map(|s| Ok(Url::parse(s)?))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Crawler {
+            config,
+            seeds,
+            state,
+            robots: HashMap::new(),
+            last_request: HashMap::new(),
+        })
+    }
+
+    /// The number of pages visited so far
+    pub fn visited(&self) -> usize {
+        self.state.visited
+    }
+
+    /// The number of URLs waiting to be visited
+    pub fn pending(&self) -> usize {
+        self.state.frontier.len()
+    }
+
+    /// The URLs that failed more than `max_retries` times and were
+    /// given up on, with the last error
+    pub fn failed(&self) -> &[(String, String)] {
+        &self.state.failed
+    }
+
+    fn in_scope(&self, url: &Url) -> bool {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return false;
+        }
+        let strip = |h: &str| h.strip_prefix("www.").unwrap_or(h).to_string();
+        match self.config.scope {
+            Scope::Any => true,
+            Scope::SameOrigin => self.seeds.iter().any(|s| s.origin() == url.origin()),
+            Scope::SameDomain => {
+                let host = match url.host_str() {
+                    None => return false,
+                    Some(h) => strip(h),
+                };
+                self.seeds.iter().filter_map(|s| s.host_str()).any(|s| {
+                    let s = strip(s);
+                    host == s || host.ends_with(&format!(".{}", s))
+                })
+            }
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(ref path) = self.config.checkpoint {
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, serde_json::to_vec(&self.state)?)?;
+            fs::rename(&tmp, path)?;
+        }
+        Ok(())
+    }
+
+    /// Wait until we may make another request to the host of `url`.
+    async fn wait_politely(&mut self, driver: &Driver, url: &Url, delay: Duration) {
+        let host = url.host_str().unwrap_or("").to_string();
+        if let Some(last) = self.last_request.get(&host) {
+            let elapsed = last.elapsed();
+            if elapsed < delay {
+                driver.sleep(delay - elapsed).await
+            }
+        }
+        self.last_request.insert(host, Instant::now());
+    }
+
+    /// Get the robots.txt rules for the origin of `url`, fetching
+    /// them with the browser the first time.
+    async fn robots(&mut self, driver: &Driver, url: &Url) -> Result<Robots> {
+        let origin = url.origin().ascii_serialization();
+        if let Some(r) = self.robots.get(&origin) {
+            return Ok(r.clone());
+        }
+        let robots_url = url.join("/robots.txt")?;
+        self.wait_politely(driver, &robots_url, self.config.politeness).await;
+        // without the cookies of the page, like any other crawler
+        let options = FetchOptions {
+            credentials: Credentials::Omit,
+            ..FetchOptions::default()
+        };
+        let res = match driver.fetch(robots_url.as_str(), options.clone()).await {
+            Ok(res) => res,
+            Err(e) if is_fatal(&e) => return Err(e),
+            // CORS blocked the request from another origin, make it
+            // from the origin of the robots.txt
+            Err(e) if driver.current_url().await?.origin() == url.origin() => {
+                return Err(e)
+            }
+            Err(_) => {
+                driver.goto(robots_url.as_str()).await?;
+                driver.fetch(robots_url.as_str(), options).await?
+            }
+        };
+        let robots = if res.status == 200 {
+            let txt = String::from_utf8_lossy(&res.body);
+            Robots::parse(&txt, &self.config.robots_agent)
+        } else {
+            Robots::default()
+        };
+        self.robots.insert(origin, robots.clone());
+        Ok(robots)
+    }
+
+    /// Navigate to `url` and handle it, `None` if robots.txt doesn't
+    /// allow it.
+    async fn visit<H: Handler>(
+        &mut self,
+        driver: &Driver,
+        handler: &mut H,
+        url: &Url,
+        depth: usize,
+    ) -> Result<Option<(Page, Visit<H::Item>)>> {
+        let mut delay = self.config.politeness;
+        if self.config.respect_robots {
+            let robots = self.robots(driver, url).await?;
+            if !robots.allowed(url) {
+                return Ok(None);
+            }
+            delay = delay.max(robots.crawl_delay.unwrap_or_default());
+        }
+        self.wait_politely(driver, url, delay).await;
+        driver.goto(url.as_str()).await?;
+        let page = Page {
+            final_url: driver.current_url().await?,
+            url: url.clone(),
+            depth,
+        };
+        let visit = handler.handle(driver, &page).await?;
+        Ok(Some((page, visit)))
+    }
+
+    /// Visit the next URL in the frontier, returning the page and the
+    /// items the handler extracted from it, or `None` if the crawl is
+    /// finished.
+    ///
+    /// If visiting the page fails the error is returned, and the crawl
+    /// can be continued by calling `step` again. The page is retried
+    /// after the rest of the frontier, unless it already failed
+    /// `max_retries` times, see `failed`. If the error is one the crawl
+    /// can't continue after, e.g. the session is gone, the page stays
+    /// first in the frontier, so resuming the crawl visits it again.
+    pub async fn step<H: Handler>(
+        &mut self,
+        driver: &Driver,
+        handler: &mut H,
+    ) -> Result<Option<(Page, Vec<H::Item>)>> {
+        loop {
+            if let Some(max) = self.config.max_pages {
+                if self.state.visited >= max {
+                    return Ok(None);
+                }
+            }
+            // the url stays in the frontier, and so in the checkpoint,
+            // until it has been handled
+            let (key, depth) = match self.state.frontier.front() {
+                None => return Ok(None),
+                Some((url, depth)) => (url.clone(), *depth),
+            };
+            let url = Url::parse(&key)?;
+            let (page, visit) = match self.visit(driver, handler, &url, depth).await {
+                Ok(Some(visited)) => visited,
+                Ok(None) => {
+                    self.state.frontier.pop_front();
+                    self.save()?;
+                    continue;
+                }
+                Err(e) if is_fatal(&e) => return Err(e),
+                Err(e) => {
+                    self.state.frontier.pop_front();
+                    let attempts = self.state.attempts.entry(key.clone()).or_default();
+                    *attempts += 1;
+                    if *attempts > self.config.max_retries {
+                        self.state.attempts.remove(&key);
+                        self.state.failed.push((key, e.to_string()));
+                    } else {
+                        self.state.frontier.push_back((key, depth));
+                    }
+                    self.save()?;
+                    return Err(e);
+                }
+            };
+            self.state.frontier.pop_front();
+            self.state.attempts.remove(&key);
+            self.state.visited += 1;
+            if depth < self.config.max_depth {
+                for link in visit.links {
+                    let link = normalize(&link);
+                    if self.in_scope(&link) && self.state.seen.insert(link.to_string()) {
+                        self.state.frontier.push_back((link.to_string(), depth + 1));
+                    }
+                }
+            }
+            self.save()?;
+            return Ok(Some((page, visit.items)));
+        }
+    }
+
+    /// Crawl until the frontier is exhausted (or `max_pages` is
+    /// reached), collecting all the extracted items. Pages that fail
+    /// are retried, and then skipped, as described in `step`. Errors
+    /// the crawl can't continue after are returned.
+    pub async fn run<H: Handler>(
+        &mut self,
+        driver: &Driver,
+        handler: &mut H,
+    ) -> Result<Vec<H::Item>> {
+        let mut items = Vec::new();
+        loop {
+            match self.step(driver, handler).await {
+                Ok(None) => break Ok(items),
+                Ok(Some((_, mut i))) => items.append(&mut i),
+                Err(e) if is_fatal(&e) => break Err(e),
+                Err(_) => (),
+            }
+        }
+    }
+
+    /// The checkpoint file, if any
+    pub fn checkpoint(&self) -> Option<&Path> {
+        self.config.checkpoint.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, CrawlConfig, Crawler, Robots, Scope};
+    use std::time::Duration;
+    use url::Url;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    const ROBOTS: &str = "
+        User-agent: *
+        Disallow: /private
+        Sitemap: https://example.com/sitemap.xml
+
+        User-agent: rusty
+        Disallow: /
+
+        User-agent: Rusty-Driver
+        User-agent: other-bot
+        Allow: /private/ok
+        Disallow: /private # comment
+        Crawl-delay: 2.5
+
+        User-agent:
+        Disallow: /empty-agent
+    ";
+
+    #[test]
+    fn robots_groups() {
+        for agent in ["rusty-driver", "RUSTY-DRIVER/0.3 (+https://example.com/)"] {
+            let r = Robots::parse(ROBOTS, agent);
+            assert_eq!(r.crawl_delay, Some(Duration::from_millis(2500)), "{}", agent);
+            assert!(r.allowed(&url("https://example.com/")));
+            assert!(r.allowed(&url("https://example.com/private/ok")));
+            assert!(!r.allowed(&url("https://example.com/private/no")));
+            assert!(r.allowed(&url("https://example.com/empty-agent")));
+        }
+        // neither a prefix nor an extension of a group's token matches
+        for agent in ["rusty-driver-ng", "driver", ""] {
+            let r = Robots::parse(ROBOTS, agent);
+            assert_eq!(r.crawl_delay, None, "{}", agent);
+            assert!(r.allowed(&url("https://example.com/")));
+            assert!(!r.allowed(&url("https://example.com/private/ok")));
+        }
+        let r = Robots::parse(ROBOTS, "rusty");
+        assert!(!r.allowed(&url("https://example.com/")));
+        assert_eq!(Robots::parse("", "rusty-driver"), Robots::default());
+    }
+
+    #[test]
+    fn robots_patterns() {
+        assert!(Robots::matches("/a", "/a"));
+        assert!(Robots::matches("/a", "/ab/c"));
+        assert!(!Robots::matches("/a", "/b"));
+        assert!(Robots::matches("/a$", "/a"));
+        assert!(!Robots::matches("/a$", "/ab"));
+        assert!(Robots::matches("/*.pdf$", "/docs/x.pdf"));
+        assert!(!Robots::matches("/*.pdf$", "/docs/x.pdf?dl=1"));
+        assert!(Robots::matches("/*/edit", "/pages/1/edit/2"));
+        assert!(Robots::matches("*", "/anything"));
+
+        let txt = "User-agent: *\nDisallow: /*.pdf$\nAllow: /a\nDisallow: /a\n\
+                   Disallow: /?q=";
+        let r = Robots::parse(txt, "rusty-driver");
+        assert!(!r.allowed(&url("https://example.com/x.pdf")));
+        assert!(r.allowed(&url("https://example.com/x.pdf?dl=1")));
+        // allow wins a tie
+        assert!(r.allowed(&url("https://example.com/a")));
+        // the query is part of the path matched
+        assert!(!r.allowed(&url("https://example.com/?q=1")));
+        assert!(r.allowed(&url("https://example.com/?p=1")));
+    }
+
+    #[test]
+    fn normalization() {
+        let n = |s| normalize(&url(s)).to_string();
+        assert_eq!(n("HTTPS://Example.COM:443/a?#frag"), "https://example.com/a");
+        let port = "http://example.com:8080/a?b=1";
+        assert_eq!(n("http://example.com:8080/a?b=1#c"), port);
+        assert_eq!(n("http://example.com"), "http://example.com/");
+    }
+
+    #[test]
+    fn scopes() {
+        let crawler = |scope| {
+            let config = CrawlConfig {
+                scope,
+                ..CrawlConfig::default()
+            };
+            Crawler::new(config, &["https://www.example.com/start"]).unwrap()
+        };
+        let cases = [
+            ("https://www.example.com/other", true, true, true),
+            ("http://www.example.com/", false, true, true),
+            ("https://example.com/", false, true, true),
+            ("https://docs.example.com/", false, true, true),
+            ("https://notexample.com/", false, false, true),
+            ("https://example.org/", false, false, true),
+            ("ftp://www.example.com/", false, false, false),
+            ("mailto:someone@example.com", false, false, false),
+        ];
+        for (link, origin, domain, any) in cases {
+            let link = url(link);
+            assert_eq!(crawler(Scope::SameOrigin).in_scope(&link), origin, "{}", link);
+            assert_eq!(crawler(Scope::SameDomain).in_scope(&link), domain, "{}", link);
+            assert_eq!(crawler(Scope::Any).in_scope(&link), any, "{}", link);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Deserializing typed values from the text of a page, e.g. form
+//! values and table cells.
+
+use serde::de::{self, value::Error, IntoDeserializer, Visitor};
+use std::str::FromStr;
+
+/// Where a `Text` comes from, which decides how booleans and blanks
+/// are read.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Source {
+    /// A submitted form value: a bool is true unless the value is
+    /// `false`, `off` or `0` (a checked checkbox submits `on`)
+    Form,
+    /// A table cell: a bool is `true`/`yes` or `false`/`no`, and a
+    /// blank cell is `None`
+    Cell,
+}
+
+/// A single string, parsed into whatever type is asked for.
+pub(crate) struct Text<'a> {
+    text: &'a str,
+    source: Source,
+}
+
+impl<'a> Text<'a> {
+    pub(crate) fn form(text: &'a str) -> Self {
+        Text {
+            text,
+            source: Source::Form,
+        }
+    }
+
+    pub(crate) fn cell(text: &'a str) -> Self {
+        Text {
+            text,
+            source: Source::Cell,
+        }
+    }
+
+    fn parse<T: FromStr>(&self) -> Result<T, Error>
+    where
+        T::Err: std::fmt::Display,
+    {
+        self.text.trim().parse().map_err(|e| {
+            de::Error::custom(format!("invalid value {:?}: {}", self.text, e))
+        })
+    }
+}
+
+macro_rules! parse_value {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Text<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str(self.text)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let text = self.text.trim().to_ascii_lowercase();
+        match (self.source, text.as_str()) {
+            (Source::Form, v) => visitor.visit_bool(!matches!(v, "false" | "off" | "0")),
+            (Source::Cell, "true" | "yes") => visitor.visit_bool(true),
+            (Source::Cell, "false" | "no") => visitor.visit_bool(false),
+            (Source::Cell, _) => Err(de::Error::custom(format!(
+                "invalid value {:?}: expected a boolean",
+                self.text
+            ))),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.source == Source::Cell && self.text.trim().is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self.text.into_deserializer())
+    }
+
+    parse_value! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map
+        struct identifier ignored_any
+    }
+}
+
+impl<'de, 'a> IntoDeserializer<'de, Error> for Text<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
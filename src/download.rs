@@ -0,0 +1,191 @@
+//! Waiting for and retrieving files downloaded by the browser.
+//!
+//! The browser must be told where to save downloads, and not to
+//! prompt for them, when the session is created, by merging
+//! `capabilities` into the session's capabilities. This works with
+//! any of the `Driver` constructors. Since the browser writes to its
+//! own file system this only works when the webdriver server runs on
+//! the same machine (or shares the download directory with it).
+//!
+//! ```text
+//! let dir = Path::new("downloads").canonicalize()?;
+//! let mut caps = Capabilities::new();
+//! download::merge_capabilities(&mut caps, download::capabilities(&dir));
+//! let driver = Driver::new_with_capabilities(url, None, caps).await?;
+//! let file = driver.expect_download(&dir, timeout, driver.click(link)).await?;
+//! ```
+
+use crate::{error::*, Driver};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use webdriver::capabilities::Capabilities;
+
+/// A file downloaded by the browser.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Download {
+    pub path: PathBuf,
+    pub bytes: Vec<u8>,
+}
+
+/// Session capabilities that make Chrome and Firefox save downloads
+/// to `dir`, which should be an absolute path, without prompting.
+pub fn capabilities(dir: &Path) -> Capabilities {
+    let dir = dir.to_string_lossy();
+    let mut c = Capabilities::new();
+    c.insert(
+        "goog:chromeOptions".to_string(),
+        json!({
+            "prefs": {
+                "download.default_directory": dir,
+                "download.prompt_for_download": false,
+                "download.directory_upgrade": true,
+                "safebrowsing.enabled": true,
+            }
+        }),
+    );
+    c.insert(
+        "moz:firefoxOptions".to_string(),
+        json!({
+            "prefs": {
+                "browser.download.dir": dir,
+                "browser.download.folderList": 2,
+                "browser.download.useDownloadDir": true,
+                "browser.download.manager.showWhenStarting": false,
+                "browser.download.always_ask_before_handling_new_types": false,
+                "browser.helperApps.neverAsk.saveToDisk":
+                    "application/octet-stream,application/pdf,application/zip,\
+                     application/json,text/csv,text/plain,application/vnd.ms-excel,\
+                     application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                "pdfjs.disabled": true,
+            }
+        }),
+    );
+    c
+}
+
+/// Merge `extra` into `caps`, merging nested objects (such as the
+/// vendor options) rather than replacing them.
+pub fn merge_capabilities(caps: &mut Capabilities, extra: Capabilities) {
+    fn merge(into: &mut Value, from: Value) {
+        match (into, from) {
+            (Value::Object(into), Value::Object(from)) => {
+                for (k, v) in from {
+                    match into.get_mut(&k) {
+                        Some(i) => merge(i, v),
+                        None => {
+                            into.insert(k, v);
+                        }
+                    }
+                }
+            }
+            (into, from) => *into = from,
+        }
+    }
+    for (k, v) in extra {
+        match caps.get_mut(&k) {
+            Some(c) => merge(c, v),
+            None => {
+                caps.insert(k, v);
+            }
+        }
+    }
+}
+
+/// Is `path` a file the browser is still writing
+fn is_partial(path: &Path) -> bool {
+    match path.file_name() {
+        None => true,
+        Some(name) => {
+            let name = name.to_string_lossy();
+            [".part", ".crdownload", ".tmp"].iter().any(|ext| name.ends_with(ext))
+        }
+    }
+}
+
+/// The size and modification time of each file in a directory
+pub(crate) type Snapshot = HashMap<PathBuf, (u64, Option<SystemTime>)>;
+
+/// The contents of `dir`, to compare against once a download has
+/// been triggered
+pub(crate) fn snapshot(dir: &Path) -> Result<Snapshot> {
+    let mut files = HashMap::new();
+    for ent in fs::read_dir(dir)? {
+        let ent = ent?;
+        let md = ent.metadata()?;
+        if md.is_file() {
+            files.insert(ent.path(), (md.len(), md.modified().ok()));
+        }
+    }
+    Ok(files)
+}
+
+impl Driver {
+    /// Run `action`, which should trigger a download (e.g. by
+    /// clicking a link), and wait up to `timeout` for the downloaded
+    /// file to be completely written to `dir`, the directory the
+    /// browser was configured to save downloads to (see
+    /// `capabilities`).
+    ///
+    /// A file counts as the download if it was created or modified
+    /// after `action` started, is not a partial file (`.part`,
+    /// `.crdownload`), no partial files started or changed since
+    /// then, and its size has stopped changing. If several files
+    /// qualify the most recently modified one is used.
+    pub async fn expect_download<F>(
+        &self,
+        dir: &Path,
+        timeout: Duration,
+        action: F,
+    ) -> Result<Download>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        let before = snapshot(dir)?;
+        action.await?;
+        wait_for_download(self, dir, &before, timeout).await
+    }
+}
+
+/// Wait for a file not in `before` to be completely written to `dir`
+pub(crate) async fn wait_for_download(
+    driver: &Driver,
+    dir: &Path,
+    before: &Snapshot,
+    timeout: Duration,
+) -> Result<Download> {
+    let deadline = Instant::now() + timeout;
+    let mut candidate: Option<(PathBuf, u64)> = None;
+    loop {
+        let now = snapshot(dir)?;
+        // files left over from before, e.g. the partial file of a
+        // crashed download, are ignored
+        let changed = now.iter().filter(|(p, md)| before.get(*p) != Some(*md));
+        let partial = changed.clone().any(|(p, _)| is_partial(p));
+        let new = changed
+            .filter(|(p, _)| !is_partial(p))
+            .max_by(|(p, (_, t)), (q, (_, u))| t.cmp(u).then_with(|| p.cmp(q)));
+        match (new, partial, candidate.take()) {
+            (Some((p, (len, _))), false, Some((prev, prev_len)))
+                if *p == prev && *len == prev_len =>
+            {
+                let bytes = fs::read(p)?;
+                break Ok(Download {
+                    path: p.clone(),
+                    bytes,
+                });
+            }
+            (Some((p, (len, _))), _, _) => candidate = Some((p.clone(), *len)),
+            (None, _, _) => (),
+        }
+        if Instant::now() >= deadline {
+            bail!("timed out waiting for download in {}", dir.display())
+        }
+        driver.sleep(Duration::from_millis(100)).await
+    }
+}
@@ -1,13 +1,15 @@
 error_chain! {
     foreign_links {
         HttpError(::http::Error);
-        HyperError(::hyper::Error);
+        HyperError(::hyper::Error) #[cfg(feature = "hyper")];
         IoErr(::std::io::Error);
         WebDriver(::webdriver::error::WebDriverError);
         BadUrl(::url::ParseError);
         InvalidJson(::serde_json::Error);
         Utf8(::std::str::Utf8Error);
-        HeaderStr(::hyper::header::ToStrError);
+        HeaderStr(::http::header::ToStrError);
+        Base64(::base64::DecodeError);
+        Zip(::zip::result::ZipError);
     }
 
     errors {
@@ -20,5 +22,15 @@ error_chain! {
             description("expected JSON"),
             display("expected JSON got ctype: {:?}", ctyp)
         }
+
+        AccessibilityViolations(n: usize, summary: String) {
+            description("accessibility violations found")
+            display("{} accessibility violations found:{}", n, summary)
+        }
+
+        CassetteMismatch(req: String) {
+            description("request not found in the cassette")
+            display("request not found in the cassette: {}", req)
+        }
     }
 }
@@ -0,0 +1,271 @@
+//! Collecting data from many elements in a single round trip.
+//!
+//! Reading the text or attributes of each element found by `find_all`
+//! costs one request per element per field, which adds up quickly,
+//! especially against a remote server. `Driver::extract` instead finds
+//! the elements and reads all the requested fields inside the page.
+//! `Driver::links` and `Driver::resources` do the same for the links
+//! and subresources of a page, e.g. to build a crawler.
+//!
+//! ```text
+//! #[derive(Deserialize)]
+//! struct Link { text: String, href: Option<String> }
+//!
+//! let spec = Extract::new().text("text").attr("href", "href");
+//! let links: Vec<Link> = driver.extract(Locator::Css("a".into()), None, &spec).await?;
+//! ```
+
+use crate::{error::*, Driver, Locator};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use webdriver::{command::LocatorParameters, common::WebElement};
+
+// Finds the elements matching the locator arguments[0] under the root
+// arguments[1] (or the document), the same way the corresponding
+// webdriver strategy would, then builds one record per element from
+// the field spec arguments[2].
+const EXTRACT_JS: &str = r#"
+    var loc = arguments[0], root = arguments[1] || document, spec = arguments[2];
+    function linkText(a) { return a.innerText.replace(/\s+/g, ' ').trim(); }
+    function find() {
+        switch (loc.using) {
+        case 'css selector':
+            return Array.prototype.slice.call(root.querySelectorAll(loc.value));
+        case 'tag name':
+            return Array.prototype.slice.call(root.getElementsByTagName(loc.value));
+        case 'xpath':
+            var r = document.evaluate(loc.value, root, null,
+                XPathResult.ORDERED_NODE_SNAPSHOT_TYPE, null);
+            var out = [];
+            for (var i = 0; i < r.snapshotLength; i++) {
+                var n = r.snapshotItem(i);
+                if (n.nodeType === Node.ELEMENT_NODE) { out.push(n); }
+            }
+            return out;
+        case 'link text':
+            return Array.prototype.filter.call(root.querySelectorAll('a'), function(a) {
+                return linkText(a) === loc.value;
+            });
+        case 'partial link text':
+            return Array.prototype.filter.call(root.querySelectorAll('a'), function(a) {
+                return linkText(a).indexOf(loc.value) >= 0;
+            });
+        default:
+            throw new Error('unsupported locator strategy ' + loc.using);
+        }
+    }
+    return find().map(function(e) {
+        var rec = {};
+        spec.forEach(function(f) {
+            var v;
+            switch (f.source) {
+            case 'text': v = e.innerText; break;
+            case 'attr': v = e.getAttribute(f.name); break;
+            case 'prop': v = e[f.name]; break;
+            case 'inner_html': v = e.innerHTML; break;
+            case 'outer_html': v = e.outerHTML; break;
+            case 'element': v = e; break;
+            }
+            rec[f.field] = v === undefined ? null : v;
+        });
+        return rec;
+    });
+"#;
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize)]
+struct Field {
+    field: String,
+    source: &'static str,
+    name: Option<String>,
+}
+
+/// The fields to collect from each element with `Driver::extract`.
+/// Each field is named by the name of the field in the output record.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Extract(Vec<Field>);
+
+impl Extract {
+    pub fn new() -> Self {
+        Extract(Vec::new())
+    }
+
+    fn field(mut self, field: &str, source: &'static str, name: Option<&str>) -> Self {
+        self.0.push(Field {
+            field: field.into(),
+            source,
+            name: name.map(String::from),
+        });
+        self
+    }
+
+    /// Collect the rendered text of the element
+    pub fn text(self, field: &str) -> Self {
+        self.field(field, "text", None)
+    }
+
+    /// Collect the attribute `attr`, `null` if it isn't present
+    pub fn attr(self, field: &str, attr: &str) -> Self {
+        self.field(field, "attr", Some(attr))
+    }
+
+    /// Collect the DOM property `prop`, which may be any JSON value
+    pub fn prop(self, field: &str, prop: &str) -> Self {
+        self.field(field, "prop", Some(prop))
+    }
+
+    /// Collect the HTML of the element, if inner is true the
+    /// `innerHTML` otherwise the `outerHTML`.
+    pub fn html(self, field: &str, inner: bool) -> Self {
+        self.field(field, if inner { "inner_html" } else { "outer_html" }, None)
+    }
+
+    /// Collect the element itself as a `WebElement`
+    pub fn element(self, field: &str) -> Self {
+        self.field(field, "element", None)
+    }
+}
+
+impl Driver {
+    /// Find all the elements matching `locator`, and collect the
+    /// fields listed in `spec` from each of them, in a single round
+    /// trip. Each element yields one record of type `T`.
+    pub async fn extract<T: DeserializeOwned>(
+        &self,
+        locator: Locator,
+        root: Option<WebElement>,
+        spec: &Extract,
+    ) -> Result<Vec<T>> {
+        let loc: LocatorParameters = locator.into();
+        self.execute_typed(EXTRACT_JS.to_string(), (loc, root, &spec.0))
+            .await
+    }
+}
+
+// Collects the raw href of every anchor and the raw URL of every
+// subresource, along with the base URL to resolve them against.
+const LINKS_JS: &str = r#"
+    var links = Array.prototype.map.call(document.querySelectorAll('a[href],area[href]'),
+        function(a) {
+            return {
+                href: a.getAttribute('href'),
+                text: (a.innerText || a.getAttribute('alt') || '').replace(/\s+/g, ' ').trim(),
+                rel: a.getAttribute('rel'),
+                target: a.getAttribute('target'),
+            };
+        });
+    return {base: document.baseURI, links: links};
+"#;
+
+const RESOURCES_JS: &str = r#"
+    var res = [];
+    function add(kind, sel, attr) {
+        document.querySelectorAll(sel).forEach(function(e) {
+            res.push({kind: kind, src: e.getAttribute(attr)});
+        });
+    }
+    add('script', 'script[src]', 'src');
+    add('stylesheet', 'link[rel~=stylesheet][href]', 'href');
+    add('image', 'img[src]', 'src');
+    add('iframe', 'iframe[src],frame[src]', 'src');
+    return {base: document.baseURI, resources: res};
+"#;
+
+/// An anchor on the page.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Link {
+    /// The absolute URL the link points to
+    pub url: url::Url,
+    pub text: String,
+    /// The link types in the `rel` attribute, e.g. "nofollow"
+    pub rel: Vec<String>,
+    pub target: Option<String>,
+}
+
+/// The kind of a subresource of the page.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceKind {
+    Script,
+    Stylesheet,
+    Image,
+    Iframe,
+}
+
+/// A subresource referenced by the page.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Resource {
+    pub kind: ResourceKind,
+    /// The absolute URL of the resource
+    pub url: url::Url,
+}
+
+#[derive(Deserialize)]
+struct RawLink {
+    href: String,
+    text: String,
+    rel: Option<String>,
+    target: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawLinks {
+    base: String,
+    links: Vec<RawLink>,
+}
+
+#[derive(Deserialize)]
+struct RawResource {
+    kind: ResourceKind,
+    src: String,
+}
+
+#[derive(Deserialize)]
+struct RawResources {
+    base: String,
+    resources: Vec<RawResource>,
+}
+
+impl Driver {
+    /// Every link (`<a href>` and `<area href>`) on the current page,
+    /// in document order. URLs are resolved against the document's
+    /// base URL, which is the current URL unless the page has a
+    /// `<base>` element, links whose href can't be resolved are
+    /// skipped.
+    pub async fn links(&self) -> Result<Vec<Link>> {
+        let raw: RawLinks = self.execute_typed(LINKS_JS.to_string(), ()).await?;
+        let base = url::Url::parse(&raw.base)?;
+        Ok(raw
+            .links
+            .into_iter()
+            .filter_map(|l| {
+                let url = base.join(l.href.trim()).ok()?;
+                Some(Link {
+                    url,
+                    text: l.text,
+                    rel: l
+                        .rel
+                        .map(|r| r.split_whitespace().map(|r| r.to_lowercase()).collect())
+                        .unwrap_or_default(),
+                    target: l.target,
+                })
+            })
+            .collect())
+    }
+
+    /// The scripts, stylesheets, images, and iframes referenced by the
+    /// current page. URLs are resolved as for `links`.
+    pub async fn resources(&self) -> Result<Vec<Resource>> {
+        let raw: RawResources =
+            self.execute_typed(RESOURCES_JS.to_string(), ()).await?;
+        let base = url::Url::parse(&raw.base)?;
+        Ok(raw
+            .resources
+            .into_iter()
+            .filter_map(|r| {
+                Some(Resource {
+                    kind: r.kind,
+                    url: base.join(r.src.trim()).ok()?,
+                })
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,168 @@
+//! Fetching resources from inside the page, with the page's cookies,
+//! origin, and credentials.
+
+use crate::{error::*, Driver, Method};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::json;
+
+// arguments: url, method, headers, base64 body or null, size limit,
+// credentials mode, callback.
+//
+// Bodies cross the webdriver boundary base64 encoded, since scripts
+// can only return JSON.
+const FETCH_JS: &str = r#"
+    var done = arguments[arguments.length - 1];
+    var url = arguments[0], method = arguments[1], headers = arguments[2];
+    var body = arguments[3], limit = arguments[4];
+    var init = {method: method, headers: headers, credentials: arguments[5]};
+    if (body !== null) {
+        var bin = atob(body), buf = new Uint8Array(bin.length);
+        for (var i = 0; i < bin.length; i++) { buf[i] = bin.charCodeAt(i); }
+        init.body = buf;
+    }
+    function b64(chunks, len) {
+        var all = new Uint8Array(len), off = 0, s = '';
+        chunks.forEach(function(c) { all.set(c, off); off += c.length; });
+        for (var i = 0; i < all.length; i += 0x8000) {
+            s += String.fromCharCode.apply(null, all.subarray(i, i + 0x8000));
+        }
+        return btoa(s);
+    }
+    fetch(url, init).then(function(r) {
+        var hdrs = [];
+        r.headers.forEach(function(v, k) { hdrs.push([k, v]); });
+        var reader = r.body ? r.body.getReader() : null;
+        var chunks = [], len = 0;
+        function read() {
+            if (reader === null) {
+                return done({status: r.status, headers: hdrs, body: ''});
+            }
+            return reader.read().then(function(c) {
+                if (c.done) {
+                    return done({status: r.status, headers: hdrs, body: b64(chunks, len)});
+                }
+                len += c.value.length;
+                if (len > limit) {
+                    reader.cancel();
+                    return done({error: 'response body larger than ' + limit + ' bytes'});
+                }
+                chunks.push(c.value);
+                return read();
+            });
+        }
+        return read();
+    }).catch(function(e) { done({error: String(e)}); });
+"#;
+
+/// Which credentials (cookies and HTTP authentication) `Driver::fetch`
+/// sends, the `credentials` option of `fetch()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Credentials {
+    /// Always, even to other origins
+    Include,
+    /// Only to the origin of the page
+    SameOrigin,
+    /// Never
+    Omit,
+}
+
+impl Credentials {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Credentials::Include => "include",
+            Credentials::SameOrigin => "same-origin",
+            Credentials::Omit => "omit",
+        }
+    }
+}
+
+/// The request to make with `Driver::fetch`.
+#[derive(Clone, Debug)]
+pub struct FetchOptions {
+    pub method: Method,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    /// Fail if the response body is larger than this many bytes.
+    pub max_size: usize,
+    /// `Include` by default, like the page's own requests to its
+    /// origin
+    pub credentials: Credentials,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        FetchOptions {
+            method: Method::GET,
+            headers: vec![],
+            body: None,
+            max_size: 16 * 1024 * 1024,
+            credentials: Credentials::Include,
+        }
+    }
+}
+
+/// The response to a `Driver::fetch`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FetchResponse {
+    pub status: u16,
+    /// Response headers, with lower case names, as exposed to the page
+    /// (i.e. without `Set-Cookie` or headers hidden by CORS).
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl FetchResponse {
+    /// The first value of the header `name` (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FetchResult {
+    Ok {
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: String,
+    },
+    Err {
+        error: String,
+    },
+}
+
+impl Driver {
+    /// Fetch `url`, resolved relative to the current page, by running
+    /// `fetch()` inside the page. The request carries the page's
+    /// cookies and origin, so it behaves like a request the page itself
+    /// made, including being subject to CORS.
+    pub async fn fetch(&self, url: &str, options: FetchOptions) -> Result<FetchResponse> {
+        let url = self.current_url().await?.join(url)?;
+        let b64 = base64::engine::general_purpose::STANDARD;
+        let args = vec![
+            json!(url.as_str()),
+            json!(options.method.as_str()),
+            json!(options.headers),
+            json!(options.body.map(|b| b64.encode(b))),
+            json!(options.max_size),
+            json!(options.credentials.as_str()),
+        ];
+        let res = self.execute_async(FETCH_JS.to_string(), args).await?;
+        match serde_json::from_value(res)? {
+            FetchResult::Ok {
+                status,
+                headers,
+                body,
+            } => Ok(FetchResponse {
+                status,
+                headers,
+                body: b64.decode(body)?,
+            }),
+            FetchResult::Err { error } => bail!("fetch {} failed: {}", url, error),
+        }
+    }
+}
@@ -0,0 +1,718 @@
+//! Helpers for filling in and reading back HTML forms.
+
+use crate::{error::*, protocol::ExtensionCommand, Driver, Locator};
+use base64::Engine;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{io::Write, path::Path};
+use webdriver::{
+    command::{SendKeysParameters, WebDriverCommand},
+    common::WebElement,
+    error::{ErrorStatus, WebDriverError},
+};
+
+// Sets each named control of arguments[0] from the object in
+// arguments[1]. Every control is resolved and every value checked
+// first, and nothing is changed unless all of them can be applied.
+// Values are set through the native value setter (so frameworks that
+// shadow `value`, e.g. React, notice the change) and input/change
+// events are fired as they would be for a user, only for controls
+// whose value actually changes.
+const FILL_FORM_JS: &str = r#"
+    var form = arguments[0], values = arguments[1];
+    var res = {missing: [], invalid: []}, plan = [];
+    function fire(el) {
+        el.dispatchEvent(new Event('input', {bubbles: true}));
+        el.dispatchEvent(new Event('change', {bubbles: true}));
+    }
+    function setValue(el, v) {
+        var proto = Object.getPrototypeOf(el);
+        var d = Object.getOwnPropertyDescriptor(proto, 'value');
+        if (d && d.set) { d.set.call(el, v); } else { el.value = v; }
+        fire(el);
+    }
+    function str(v) { return v === null ? '' : String(v); }
+    function check(el, on) {
+        plan.push(function() {
+            if (el.checked !== on) { el.checked = on; fire(el); }
+        });
+    }
+    function select(el, v) {
+        var want = Array.isArray(v) ? v.map(str) : [str(v)];
+        if (!el.multiple && want.length > 1) { return false; }
+        var on = [], found = 0;
+        for (var i = 0; i < el.options.length; i++) {
+            on.push(want.indexOf(el.options[i].value) >= 0);
+            if (on[i]) { found++; }
+        }
+        if (found !== want.length) { return false; }
+        plan.push(function() {
+            var changed = false;
+            for (var i = 0; i < el.options.length; i++) {
+                if (el.options[i].selected !== on[i]) {
+                    el.options[i].selected = on[i];
+                    changed = true;
+                }
+            }
+            if (changed) { fire(el); }
+        });
+        return true;
+    }
+    function control(el, v) {
+        var tag = el.tagName.toLowerCase();
+        var type = (el.type || '').toLowerCase();
+        if (tag === 'select') {
+            return select(el, v);
+        } else if (type === 'checkbox' || type === 'radio') {
+            if (typeof v === 'boolean') { check(el, v); }
+            else if (Array.isArray(v)) { check(el, v.map(str).indexOf(el.value) >= 0); }
+            else { check(el, str(v) === el.value); }
+            return true;
+        } else if (type === 'file' || typeof v === 'object' && v !== null) {
+            // arrays are objects too
+            return false;
+        } else {
+            var s = str(v);
+            plan.push(function() { if (el.value !== s) { setValue(el, s); } });
+            return true;
+        }
+    }
+    function group(els, v) {
+        var boxes = els.every(function(e) {
+            return e.type === 'checkbox' || e.type === 'radio';
+        });
+        if (!boxes || typeof v === 'boolean') { return false; }
+        var want = Array.isArray(v) ? v.map(str) : [str(v)];
+        var found = 0;
+        els.forEach(function(e) {
+            var on = want.indexOf(e.value) >= 0;
+            if (on) { found++; }
+            check(e, on);
+        });
+        return found === want.length;
+    }
+    Object.keys(values).forEach(function(name) {
+        var ctl = form.elements.namedItem(name);
+        if (!ctl) { res.missing.push(name); return; }
+        var ok = ctl instanceof RadioNodeList
+            ? group(Array.prototype.slice.call(ctl), values[name])
+            : control(ctl, values[name]);
+        if (!ok) { res.invalid.push(name); }
+    });
+    if (res.missing.length === 0 && res.invalid.length === 0) {
+        plan.forEach(function(apply) { apply(); });
+    }
+    return res;
+"#;
+
+// Collects the form data set of arguments[0] as the browser would
+// submit it, optionally as submitted by the button arguments[1].
+// Browsers that predate the FormData submitter argument ignore it, so
+// in that case the submitter's entry is appended by hand.
+const FORM_VALUES_JS: &str = r#"
+    var form = arguments[0], submitter = arguments[1];
+    var fd = submitter ? new FormData(form, submitter) : new FormData(form);
+    if (submitter && submitter.name) {
+        var present = fd.getAll(submitter.name).indexOf(submitter.value) >= 0;
+        if (!present) { fd.append(submitter.name, submitter.value); }
+    }
+    var res = [];
+    fd.forEach(function(v, k) {
+        res.push([k, typeof v === 'string' ? v : v.name]);
+    });
+    return res;
+"#;
+
+/// The successful controls of a form, in document order, as they
+/// would be submitted by the browser. File inputs are represented by
+/// the file name.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct FormValues(pub Vec<(String, String)>);
+
+impl FormValues {
+    /// All the values submitted for `name`
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.0.iter().filter(move |(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    /// The first value submitted for `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    /// Deserialize the values into `T`, typically a struct with a
+    /// field per control name. The shape is driven by the field types
+    /// rather than by how many values happen to be submitted:
+    ///
+    /// - sequences (e.g. `Vec<String>` for a checkbox group or multi
+    ///   select) get every value of the name, possibly none.
+    /// - `Option`s are `None` when the name isn't submitted.
+    /// - `bool`s are whether the name is submitted (e.g. a checkbox
+    ///   is checked), unless its value is `false`, `off` or `0`.
+    /// - numbers are parsed from the value.
+    /// - other fields take the value, or the last one if several
+    ///   controls share the name (as most servers do).
+    ///
+    /// Untyped targets, e.g. a `HashMap<String, Value>`, get a string
+    /// for names submitted once and a sequence otherwise.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T> {
+        T::deserialize(de::Form(&self.0)).map_err(|e| Error::from(e.to_string()))
+    }
+
+    /// Encode the values as `application/x-www-form-urlencoded`, e.g.
+    /// to replay the submission over plain HTTP.
+    pub fn to_urlencoded(&self) -> String {
+        url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(self.0.iter())
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+struct FillResult {
+    missing: Vec<String>,
+    invalid: Vec<String>,
+}
+
+impl Driver {
+    /// Fill in the form `form` from `values`, which must serialize
+    /// to a map (e.g. a struct) from control names to values.
+    ///
+    /// - text like inputs (including date, number, etc) and textareas
+    ///   are set to the value converted to a string.
+    /// - selects select the option with the given value, multi selects
+    ///   accept a sequence of values.
+    /// - a checkbox is checked or unchecked by a `bool`, a group of
+    ///   checkboxes sharing a name is set by a sequence of the values
+    ///   to check.
+    /// - a group of radios selects the radio with the given value.
+    /// - a `null` value, e.g. a `None` field, leaves its control alone.
+    ///
+    /// Values are set in a way that fires the `input` and `change`
+    /// events a user interaction would, controls that already have
+    /// the value are left alone. It is an error if a field has no
+    /// matching control, or a value can't be applied to its control
+    /// (e.g. an option that doesn't exist), in which case the form is
+    /// left unchanged.
+    pub async fn fill_form<T: Serialize + ?Sized>(
+        &self,
+        form: WebElement,
+        values: &T,
+    ) -> Result<()> {
+        let mut values = match serde_json::to_value(values)? {
+            Value::Object(o) => o,
+            v => bail!("form values must serialize to a map, got {}", v),
+        };
+        values.retain(|_, v| !v.is_null());
+        let args = vec![serde_json::to_value(form)?, Value::Object(values)];
+        let res: FillResult = serde_json::from_value(
+            self.execute(FILL_FORM_JS.to_string(), args).await?,
+        )?;
+        if !res.missing.is_empty() {
+            bail!("no form control named {}", res.missing.join(", "))
+        }
+        if !res.invalid.is_empty() {
+            bail!("invalid value for form control {}", res.invalid.join(", "))
+        }
+        Ok(())
+    }
+
+    /// Read the current values of the form `form`, as the browser
+    /// would submit them if the submit button `submitter` was clicked
+    /// (or if `submitter` is `None`, if the form was submitted
+    /// directly, see `submit_direct`).
+    pub async fn form_values(
+        &self,
+        form: WebElement,
+        submitter: Option<WebElement>,
+    ) -> Result<FormValues> {
+        let submitter = match submitter {
+            None => Value::Null,
+            Some(elt) => serde_json::to_value(elt)?,
+        };
+        let args = vec![serde_json::to_value(form)?, submitter];
+        let res = self.execute(FORM_VALUES_JS.to_string(), args).await?;
+        Ok(FormValues(serde_json::from_value(res)?))
+    }
+
+    /// Upload the file at `path` through the file input `eid`. Use
+    /// `upload_files` to upload several files through a `multiple`
+    /// input.
+    pub async fn upload_file(&self, eid: WebElement, path: &Path) -> Result<()> {
+        self.upload_files(eid, &[path]).await
+    }
+
+    /// Upload the files at `paths` through the file input `eid`.
+    ///
+    /// When the webdriver server supports Selenium's file upload
+    /// extension (e.g. Selenium Grid) each file is first sent to the
+    /// server, so this works even when the browser runs on another
+    /// machine. Otherwise the browser is assumed to share our file
+    /// system and the local paths are used directly.
+    pub async fn upload_files(&self, eid: WebElement, paths: &[&Path]) -> Result<()> {
+        let mut remote = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = path.canonicalize()?;
+            match self.upload_to_server(&path).await {
+                Ok(p) => remote.push(p),
+                Err(Error(ErrorKind::WebDriver(e), _))
+                    if e.error == ErrorStatus::UnknownCommand
+                        || e.error == ErrorStatus::UnknownMethod =>
+                {
+                    remote.push(path.to_string_lossy().into_owned())
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let p = SendKeysParameters {
+            text: remote.join("\n"),
+        };
+        self.issue_cmd(&WebDriverCommand::ElementSendKeys(eid, p)).await?;
+        Ok(())
+    }
+
+    /// Send a file to the webdriver server zipped, returning its path
+    /// on the server.
+    async fn upload_to_server(&self, path: &Path) -> Result<String> {
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => bail!("not a file {}", path.display()),
+        };
+        let contents = std::fs::read(path)?;
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file(name, zip::write::FileOptions::default())?;
+        zip.write_all(&contents)?;
+        let zip = zip.finish()?.into_inner();
+        let file = base64::engine::general_purpose::STANDARD.encode(zip);
+        let ext = ExtensionCommand::UploadFile(serde_json::json!({ "file": file }));
+        match self.issue_cmd(&WebDriverCommand::Extension(ext)).await? {
+            Value::String(p) => Ok(p),
+            v => bail!(ErrorKind::NotW3C(v)),
+        }
+    }
+}
+
+// Selects (or with arguments[3] false, deselects) the option of the
+// select arguments[0] matching arguments[2] by arguments[1] ("value",
+// "text" or "index"), firing events only if the selection changed.
+// Returns false if no option matched.
+const SELECT_JS: &str = r#"
+    var sel = arguments[0], how = arguments[1], key = arguments[2];
+    var on = arguments[3];
+    function norm(s) { return s.replace(/\s+/g, ' ').trim(); }
+    for (var i = 0; i < sel.options.length; i++) {
+        var o = sel.options[i];
+        var m = how === 'value' ? o.value === key
+            : how === 'text' ? norm(o.text) === key
+            : i === key;
+        if (m) {
+            if (o.selected !== on) {
+                o.selected = on;
+                sel.dispatchEvent(new Event('input', {bubbles: true}));
+                sel.dispatchEvent(new Event('change', {bubbles: true}));
+            }
+            return true;
+        }
+    }
+    return false;
+"#;
+
+/// A wrapper over a `<select>` element that selects options the way a
+/// user would, firing the `input` and `change` events.
+#[derive(Clone)]
+pub struct Select<'a> {
+    driver: &'a Driver,
+    elt: WebElement,
+}
+
+impl<'a> Select<'a> {
+    /// Wrap the `<select>` element `elt`
+    pub fn new(driver: &'a Driver, elt: WebElement) -> Self {
+        Select { driver, elt }
+    }
+
+    /// The wrapped `<select>` element
+    pub fn element(&self) -> &WebElement {
+        &self.elt
+    }
+
+    /// Does this select allow more than one option to be selected
+    pub async fn is_multiple(&self) -> Result<bool> {
+        let js = "return arguments[0].multiple".to_string();
+        self.driver.execute_typed(js, (&self.elt,)).await
+    }
+
+    /// All the `<option>` elements of this select
+    pub async fn options(&self) -> Result<Vec<WebElement>> {
+        let l = Locator::TagName("option".into());
+        self.driver.find_all(l, Some(self.elt.clone())).await
+    }
+
+    /// The currently selected `<option>` elements
+    pub async fn selected(&self) -> Result<Vec<WebElement>> {
+        let js = "return Array.prototype.slice.call(arguments[0].selectedOptions)";
+        self.driver.execute_typed(js.to_string(), (&self.elt,)).await
+    }
+
+    async fn set(&self, how: &str, key: Value, on: bool) -> Result<()> {
+        let args = (&self.elt, how, &key, on);
+        if !self.driver.execute_typed::<_, bool>(SELECT_JS.to_string(), args).await? {
+            bail!(ErrorKind::WebDriver(WebDriverError::new(
+                ErrorStatus::NoSuchElement,
+                format!("no option with {} {}", how, key)
+            )))
+        }
+        Ok(())
+    }
+
+    /// Select the option with the given `value` attribute
+    pub async fn select_by_value(&self, value: &str) -> Result<()> {
+        self.set("value", Value::String(value.into()), true).await
+    }
+
+    /// Select the option with the given text (compared with whitespace
+    /// normalized)
+    pub async fn select_by_visible_text(&self, text: &str) -> Result<()> {
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        self.set("text", Value::String(text), true).await
+    }
+
+    /// Select the option at `index`
+    pub async fn select_by_index(&self, index: usize) -> Result<()> {
+        self.set("index", Value::from(index), true).await
+    }
+
+    /// Deselect the option with the given `value` attribute. Only
+    /// multi selects can have their options deselected.
+    pub async fn deselect_by_value(&self, value: &str) -> Result<()> {
+        if !self.is_multiple().await? {
+            bail!("only the options of a multi select can be deselected")
+        }
+        self.set("value", Value::String(value.into()), false).await
+    }
+
+    /// Deselect all the options of a multi select
+    pub async fn deselect_all(&self) -> Result<()> {
+        if !self.is_multiple().await? {
+            bail!("only the options of a multi select can be deselected")
+        }
+        let js = r#"
+            var sel = arguments[0], changed = false;
+            for (var i = 0; i < sel.options.length; i++) {
+                if (sel.options[i].selected) {
+                    sel.options[i].selected = false;
+                    changed = true;
+                }
+            }
+            if (changed) {
+                sel.dispatchEvent(new Event('input', {bubbles: true}));
+                sel.dispatchEvent(new Event('change', {bubbles: true}));
+            }
+        "#;
+        self.driver.execute_typed(js.to_string(), (&self.elt,)).await
+    }
+}
+
+/// A serde `Deserializer` over the values of a form, see
+/// `FormValues::deserialize`.
+mod de {
+    use crate::de::Text;
+    use serde::de::{
+        self, value::Error, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess,
+        Visitor,
+    };
+    use std::vec;
+
+    pub(super) struct Form<'a>(pub(super) &'a [(String, String)]);
+
+    impl<'a> Form<'a> {
+        fn values(&self, name: &str) -> Vec<&'a str> {
+            self.0
+                .iter()
+                .filter(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str())
+                .collect()
+        }
+
+        fn fields(self, expected: &[&'static str]) -> Fields<'a> {
+            let mut names: Vec<&'a str> = vec![];
+            for (k, _) in self.0 {
+                if !names.contains(&k.as_str()) {
+                    names.push(k);
+                }
+            }
+            // names that weren't submitted still reach the struct,
+            // e.g. as an empty sequence or `None`
+            let missing = expected
+                .iter()
+                .filter(|f| !names.contains(f))
+                .map(|f| (*f, vec![]))
+                .collect::<Vec<_>>();
+            let mut fields = names
+                .into_iter()
+                .map(|n| (n, self.values(n)))
+                .collect::<Vec<_>>();
+            fields.extend(missing);
+            Fields {
+                fields: fields.into_iter(),
+                current: None,
+            }
+        }
+    }
+
+    impl<'de, 'a> de::Deserializer<'de> for Form<'a> {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_map(self.fields(&[]))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_map(self.fields(fields))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    struct Fields<'a> {
+        fields: vec::IntoIter<(&'a str, Vec<&'a str>)>,
+        current: Option<(&'a str, Vec<&'a str>)>,
+    }
+
+    impl<'de, 'a> MapAccess<'de> for Fields<'a> {
+        type Error = Error;
+
+        fn next_key_seed<K: DeserializeSeed<'de>>(
+            &mut self,
+            seed: K,
+        ) -> Result<Option<K::Value>, Error> {
+            match self.fields.next() {
+                None => Ok(None),
+                Some((name, values)) => {
+                    self.current = Some((name, values));
+                    seed.deserialize(name.into_deserializer()).map(Some)
+                }
+            }
+        }
+
+        fn next_value_seed<V: DeserializeSeed<'de>>(
+            &mut self,
+            seed: V,
+        ) -> Result<V::Value, Error> {
+            let (name, values) = self.current.take().expect("value before key");
+            seed.deserialize(Values { name, values })
+        }
+    }
+
+    /// All the values submitted for `name`
+    struct Values<'a> {
+        name: &'a str,
+        values: Vec<&'a str>,
+    }
+
+    impl<'a> Values<'a> {
+        fn last(self) -> Result<Text<'a>, Error> {
+            match self.values.last() {
+                None => Err(de::Error::custom(format!("missing field `{}`", self.name))),
+                Some(v) => Ok(Text::form(v)),
+            }
+        }
+    }
+
+    macro_rules! last_value {
+        ($($method:ident)*) => {
+            $(
+                fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                    self.last()?.$method(visitor)
+                }
+            )*
+        }
+    }
+
+    impl<'de, 'a> de::Deserializer<'de> for Values<'a> {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            if self.values.len() == 1 {
+                visitor.visit_str(self.values[0])
+            } else {
+                self.deserialize_seq(visitor)
+            }
+        }
+
+        fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_seq(Seq(self.values.into_iter()))
+        }
+
+        fn deserialize_tuple<V: Visitor<'de>>(
+            self,
+            _len: usize,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.deserialize_seq(visitor)
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            if self.values.is_empty() {
+                visitor.visit_none()
+            } else {
+                visitor.visit_some(self)
+            }
+        }
+
+        fn deserialize_bool<V: Visitor<'de>>(
+            self,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            match self.values.last() {
+                None => visitor.visit_bool(false),
+                Some(v) => visitor.visit_bool(!matches!(*v, "false" | "off" | "0")),
+            }
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            name: &'static str,
+            variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.last()?.deserialize_enum(name, variants, visitor)
+        }
+
+        last_value! {
+            deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+            deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32
+            deserialize_u64 deserialize_u128 deserialize_f32 deserialize_f64
+            deserialize_char deserialize_str deserialize_string
+            deserialize_bytes deserialize_byte_buf deserialize_identifier
+        }
+
+        serde::forward_to_deserialize_any! {
+            unit unit_struct tuple_struct map struct ignored_any
+        }
+    }
+
+    struct Seq<'a>(vec::IntoIter<&'a str>);
+
+    impl<'de, 'a> SeqAccess<'de> for Seq<'a> {
+        type Error = Error;
+
+        fn next_element_seed<T: DeserializeSeed<'de>>(
+            &mut self,
+            seed: T,
+        ) -> Result<Option<T::Value>, Error> {
+            match self.0.next() {
+                None => Ok(None),
+                Some(v) => seed.deserialize(Text::form(v)).map(Some),
+            }
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            Some(self.0.len())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FormValues;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    fn form(pairs: &[(&str, &str)]) -> FormValues {
+        FormValues(pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect())
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Order {
+        name: String,
+        quantity: u32,
+        price: f64,
+        toppings: Vec<String>,
+        gift: bool,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn checkbox_groups() {
+        let base = [("name", "Ann"), ("quantity", "2"), ("price", "9.5")];
+        for checked in [vec![], vec!["ham"], vec!["ham", "egg"]] {
+            let mut pairs = base.to_vec();
+            pairs.extend(checked.iter().map(|v| ("toppings", *v)));
+            let order: Order = form(&pairs).deserialize().unwrap();
+            assert_eq!(order.toppings, checked);
+            assert_eq!(order.name, "Ann");
+            assert_eq!(order.quantity, 2);
+            assert_eq!(order.price, 9.5);
+            assert!(!order.gift);
+            assert_eq!(order.note, None);
+        }
+    }
+
+    #[test]
+    fn typed_fields() {
+        let pairs = [
+            ("name", "Ann"),
+            ("name", "Bob"),
+            ("quantity", "12"),
+            ("price", "0"),
+            ("gift", "on"),
+            ("note", ""),
+        ];
+        let order: Order = form(&pairs).deserialize().unwrap();
+        // the last of several values wins
+        assert_eq!(order.name, "Bob");
+        assert_eq!(order.quantity, 12);
+        assert!(order.gift);
+        assert_eq!(order.note.as_deref(), Some(""));
+        assert!(order.toppings.is_empty());
+
+        let bad = [("name", "Ann"), ("quantity", "two"), ("price", "1")];
+        let err = form(&bad).deserialize::<Order>().unwrap_err();
+        assert!(err.to_string().contains("two"), "{}", err);
+        let missing = form(&[("quantity", "1"), ("price", "1")]);
+        let err = missing.deserialize::<Order>().unwrap_err();
+        assert!(err.to_string().contains("name"), "{}", err);
+    }
+
+    #[test]
+    fn enums_and_untyped() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        #[serde(rename_all = "lowercase")]
+        enum Size {
+            Small,
+            Large,
+        }
+        #[derive(Deserialize)]
+        struct Pick {
+            size: Size,
+        }
+        let pick: Pick = form(&[("size", "large")]).deserialize().unwrap();
+        assert_eq!(pick.size, Size::Large);
+
+        let m: HashMap<String, serde_json::Value> =
+            form(&[("a", "1"), ("b", "2"), ("b", "3")]).deserialize().unwrap();
+        assert_eq!(m["a"], "1");
+        assert_eq!(m["b"], serde_json::json!(["2", "3"]));
+    }
+}
@@ -11,17 +11,39 @@
 #[macro_use]
 extern crate error_chain;
 
+pub mod a11y;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod cassette;
+pub mod crawler;
+mod de;
+pub mod download;
 pub mod error;
+pub mod extract;
+pub mod fetch;
+pub mod form;
+pub mod pool;
 mod protocol;
+pub mod selector;
+pub mod table;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transport;
 
-use crate::error::*;
-pub use hyper::Method;
-use protocol::Client;
+use crate::{cassette::Recorder, error::*};
+use base64::Engine;
+pub use http::Method;
+use protocol::{Client, ExtensionCommand};
+use transport::Transport;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
-use std::time::Duration;
-use tokio::time::sleep;
+use std::{sync::Arc, time::Duration};
 use webdriver::{
-    command::{SwitchToFrameParameters, SwitchToWindowParameters, WebDriverCommand},
+    capabilities::Capabilities,
+    command::{
+        PrintMargins, PrintOrientation, PrintPage, PrintParameters,
+        SwitchToFrameParameters, SwitchToWindowParameters, WebDriverCommand,
+    },
     common::{FrameId, WebElement, ELEMENT_KEY},
     error::{ErrorStatus, WebDriverError},
 };
@@ -30,12 +52,31 @@ use webdriver::{
 pub enum Locator {
     Css(String),
     LinkText(String),
+    PartialLinkText(String),
+    TagName(String),
     XPath(String),
 }
 
-impl Into<webdriver::command::LocatorParameters> for Locator {
-    fn into(self) -> webdriver::command::LocatorParameters {
-        match self {
+impl Locator {
+    /// Match the element with the given id
+    pub fn id(id: &str) -> Self {
+        selector::Css::id(id).into()
+    }
+
+    /// Match elements with the given `name` attribute
+    pub fn name(name: &str) -> Self {
+        selector::Css::any().attr_eq("name", name).into()
+    }
+
+    /// Match elements with the given class
+    pub fn class_name(class: &str) -> Self {
+        selector::Css::class(class).into()
+    }
+}
+
+impl From<Locator> for webdriver::command::LocatorParameters {
+    fn from(l: Locator) -> Self {
+        match l {
             Locator::Css(s) => webdriver::command::LocatorParameters {
                 using: webdriver::common::LocatorStrategy::CSSSelector,
                 value: s,
@@ -48,11 +89,314 @@ impl Into<webdriver::command::LocatorParameters> for Locator {
                 using: webdriver::common::LocatorStrategy::LinkText,
                 value: s,
             },
+            Locator::PartialLinkText(s) => webdriver::command::LocatorParameters {
+                using: webdriver::common::LocatorStrategy::PartialLinkText,
+                value: s,
+            },
+            Locator::TagName(s) => webdriver::command::LocatorParameters {
+                using: webdriver::common::LocatorStrategy::TagName,
+                value: s,
+            },
+        }
+    }
+}
+
+/// Page orientation used when printing.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+/// Options for `Driver::print_pdf`. All lengths are in centimeters,
+/// the defaults are those of the W3C Print command (US letter,
+/// portrait, 1cm margins, scale 1.0, no backgrounds).
+#[derive(Clone, PartialEq, Debug)]
+pub struct PrintOptions {
+    pub orientation: Orientation,
+    /// Scale factor for the page contents, between 0.1 and 2.0
+    pub scale: f64,
+    /// Print background colors and images
+    pub background: bool,
+    /// Page (width, height)
+    pub page_size: (f64, f64),
+    /// Margins (top, bottom, left, right)
+    pub margins: (f64, f64, f64, f64),
+    /// Pages to print, e.g. `"1-3"` or `"5"`. Empty means all pages.
+    pub page_ranges: Vec<String>,
+    /// Shrink the content to fit the page width
+    pub shrink_to_fit: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        let p = PrintParameters::default();
+        PrintOptions {
+            orientation: Orientation::Portrait,
+            scale: p.scale,
+            background: p.background,
+            page_size: (p.page.width, p.page.height),
+            margins: (p.margin.top, p.margin.bottom, p.margin.left, p.margin.right),
+            page_ranges: p.page_ranges,
+            shrink_to_fit: p.shrink_to_fit,
+        }
+    }
+}
+
+impl From<PrintOptions> for PrintParameters {
+    fn from(o: PrintOptions) -> Self {
+        let (width, height) = o.page_size;
+        let (top, bottom, left, right) = o.margins;
+        PrintParameters {
+            orientation: match o.orientation {
+                Orientation::Portrait => PrintOrientation::Portrait,
+                Orientation::Landscape => PrintOrientation::Landscape,
+            },
+            scale: o.scale,
+            background: o.background,
+            page: PrintPage { width, height },
+            margin: PrintMargins {
+                top,
+                bottom,
+                left,
+                right,
+            },
+            page_ranges: o.page_ranges,
+            shrink_to_fit: o.shrink_to_fit,
+        }
+    }
+}
+
+/// The `document.readyState` of a page, in the order a loading
+/// page passes through them.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadyState {
+    Loading,
+    Interactive,
+    Complete,
+}
+
+/// The navigation timing entry of a page load, as reported by
+/// `performance.getEntriesByType('navigation')`. All times are in
+/// milliseconds relative to the start of the navigation.
+#[derive(Clone, PartialEq, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct NavigationTiming {
+    /// The URL of the document
+    pub name: String,
+    /// The navigation type, e.g. "navigate", "reload", "back_forward"
+    #[serde(rename = "type")]
+    pub navigation_type: String,
+    pub duration: f64,
+    pub redirect_count: u64,
+    pub redirect_start: f64,
+    pub redirect_end: f64,
+    pub fetch_start: f64,
+    pub domain_lookup_start: f64,
+    pub domain_lookup_end: f64,
+    pub connect_start: f64,
+    pub connect_end: f64,
+    pub secure_connection_start: f64,
+    pub request_start: f64,
+    pub response_start: f64,
+    pub response_end: f64,
+    pub dom_interactive: f64,
+    pub dom_content_loaded_event_start: f64,
+    pub dom_content_loaded_event_end: f64,
+    pub dom_complete: f64,
+    pub load_event_start: f64,
+    pub load_event_end: f64,
+    pub transfer_size: u64,
+    pub encoded_body_size: u64,
+    pub decoded_body_size: u64,
+}
+
+/// The key identifying a shadow root reference in the W3C protocol.
+pub const SHADOW_ROOT_KEY: &str = "shadow-6066-11e4-a52e-4f735466cecf";
+
+/// A handle to the shadow root of an element, obtained from
+/// `Driver::shadow_root`. Elements inside the shadow tree can only be
+/// found through this handle.
+#[derive(Clone)]
+pub struct ShadowRoot<'a> {
+    driver: &'a Driver,
+    id: String,
+}
+
+impl<'a> ShadowRoot<'a> {
+    /// The webdriver id of this shadow root
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn locator_json(locator: Locator) -> Result<Value> {
+        let p: webdriver::command::LocatorParameters = locator.into();
+        Ok(serde_json::to_value(p)?)
+    }
+
+    /// Find the first element in the shadow tree that matches the
+    /// specified selector. Note that the XPath strategy is not
+    /// supported inside shadow trees.
+    pub async fn find(&self, locator: Locator) -> Result<WebElement> {
+        let ext = ExtensionCommand::FindShadowElement(
+            self.id.clone(),
+            Self::locator_json(locator)?,
+        );
+        let res = self.driver.issue_cmd(&WebDriverCommand::Extension(ext)).await?;
+        self.driver.parse_lookup(res)
+    }
+
+    /// Find all the elements in the shadow tree that match the
+    /// specified selector.
+    pub async fn find_all(&self, locator: Locator) -> Result<Vec<WebElement>> {
+        let ext = ExtensionCommand::FindShadowElements(
+            self.id.clone(),
+            Self::locator_json(locator)?,
+        );
+        match self.driver.issue_cmd(&WebDriverCommand::Extension(ext)).await? {
+            Value::Array(a) => Ok(a
+                .into_iter()
+                .map(|e| self.driver.parse_lookup(e))
+                .collect::<Result<Vec<WebElement>>>()?),
+            r => bail!(ErrorKind::NotW3C(r)),
         }
     }
 }
 
-pub struct Driver(Client);
+impl<'a> Serialize for ShadowRoot<'a> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut m = serializer.serialize_map(Some(1))?;
+        m.serialize_entry(SHADOW_ROOT_KEY, &self.id)?;
+        m.end()
+    }
+}
+
+/// A selector matching the elements that may have the implicit or
+/// explicit WAI-ARIA `role`.
+fn role_candidates(role: &str) -> Locator {
+    let implicit = match role {
+        "button" => "button,input[type=button],input[type=submit],\
+                     input[type=reset],input[type=image],summary",
+        "link" => "a[href],area[href]",
+        "checkbox" => "input[type=checkbox]",
+        "radio" => "input[type=radio]",
+        "textbox" => "input:not([type]),input[type=text],input[type=email],\
+                      input[type=tel],input[type=url],textarea",
+        "searchbox" => "input[type=search]",
+        "combobox" => "select,input[list]",
+        "listbox" => "select[multiple],datalist",
+        "option" => "option",
+        "heading" => "h1,h2,h3,h4,h5,h6",
+        "img" => "img",
+        "list" => "ul,ol,menu",
+        "listitem" => "li",
+        "table" => "table",
+        "row" => "tr",
+        "cell" => "td",
+        "columnheader" | "rowheader" => "th",
+        "navigation" => "nav",
+        "main" => "main",
+        "form" => "form",
+        "dialog" => "dialog",
+        "slider" => "input[type=range]",
+        "spinbutton" => "input[type=number]",
+        "progressbar" => "progress",
+        _ => return Locator::Css("*".into()),
+    };
+    let explicit = selector::Css::any().attr_eq("role", role);
+    Locator::Css(format!("{},{}", implicit, explicit.as_str()))
+}
+
+/// The response of the webdriver status command.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+pub struct ServerStatus {
+    /// Whether the server can create new sessions. Servers that
+    /// predate the W3C spec don't report this and are assumed ready.
+    #[serde(default = "ServerStatus::default_ready")]
+    pub ready: bool,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl ServerStatus {
+    fn default_ready() -> bool {
+        true
+    }
+}
+
+/// The browsing context commands are directed at: a window, and the
+/// path of frames within it. `None` is the session's initial window.
+#[derive(Clone, PartialEq, Debug, Default)]
+struct Context {
+    window: Option<String>,
+    frames: Vec<WebElement>,
+}
+
+impl Context {
+    /// Update the context to reflect the successful execution of `cmd`
+    fn update(&mut self, cmd: &protocol::Cmd) {
+        match cmd {
+            WebDriverCommand::SwitchToFrame(SwitchToFrameParameters {
+                id: Some(FrameId::Element(ref e)),
+            }) => self.frames.push(e.clone()),
+            WebDriverCommand::SwitchToFrame(..) => self.frames.clear(),
+            WebDriverCommand::SwitchToParentFrame => {
+                self.frames.pop();
+            }
+            WebDriverCommand::SwitchToWindow(ref p) => {
+                self.window = Some(p.handle.clone());
+                self.frames.clear()
+            }
+            // navigation always happens in the top level browsing
+            // context, and leaves focus there
+            WebDriverCommand::Get(..)
+            | WebDriverCommand::GoBack
+            | WebDriverCommand::GoForward
+            | WebDriverCommand::Refresh => self.frames.clear(),
+            _ => (),
+        }
+    }
+}
+
+struct Inner {
+    client: Client,
+    /// The context the browser is currently in, `None` if unknown
+    current: tokio::sync::Mutex<Option<Context>>,
+}
+
+/// A handle to a webdriver session.
+///
+/// `Driver` is cheap to clone, and clones can be used concurrently
+/// from many tasks. Each handle tracks its own browsing context (the
+/// window and frame it has switched to), and commands are serialized,
+/// with the browser switched back to the issuing handle's context if
+/// another handle moved it. So one task calling `switch_to_frame`
+/// does not affect the elements another task finds. A clone starts
+/// out in the context of the handle it was cloned from.
+///
+/// The session is closed when the last handle is dropped.
+pub struct Driver {
+    inner: Arc<Inner>,
+    context: std::sync::Mutex<Context>,
+}
+
+impl Clone for Driver {
+    fn clone(&self) -> Self {
+        Driver {
+            inner: self.inner.clone(),
+            context: std::sync::Mutex::new(self.context.lock().unwrap().clone()),
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn assert_send_sync<T: Send + Sync>() {}
+const _: fn() = assert_send_sync::<Driver>;
 
 macro_rules! generate_wait_for_find {
     ($name:ident, $search_fn:ident, $return_typ:ty) => {
@@ -67,7 +411,7 @@ macro_rules! generate_wait_for_find {
                     Ok(e) => break Ok(e),
                     Err(Error(ErrorKind::WebDriver(
                         WebDriverError {error: ErrorStatus::NoSuchElement, ..}
-                    ), _)) => sleep(Duration::from_millis(100)).await,
+                    ), _)) => self.sleep(Duration::from_millis(100)).await,
                     Err(e) => break Err(e)
                 }
             }
@@ -78,7 +422,142 @@ macro_rules! generate_wait_for_find {
 impl Driver {
     /// Create a new webdriver session on the specified server
     pub async fn new(webdriver_url: &str, user_agent: Option<String>) -> Result<Self> {
-        Ok(Driver(Client::new(webdriver_url, user_agent).await?))
+        Self::new_with_capabilities(webdriver_url, user_agent, Capabilities::new()).await
+    }
+
+    /// Create a new webdriver session on the specified server,
+    /// requesting the additional `capabilities`, e.g. vendor specific
+    /// browser options.
+    pub async fn new_with_capabilities(
+        webdriver_url: &str,
+        user_agent: Option<String>,
+        capabilities: Capabilities,
+    ) -> Result<Self> {
+        let transport = transport::default_transport()?;
+        Self::connect(webdriver_url, user_agent, capabilities, transport, None).await
+    }
+
+    /// Create a new webdriver session on the specified server,
+    /// sending all requests through `transport`, see the `transport`
+    /// module.
+    pub async fn new_with_transport(
+        webdriver_url: &str,
+        user_agent: Option<String>,
+        capabilities: Capabilities,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Self> {
+        Self::connect(webdriver_url, user_agent, capabilities, transport, None).await
+    }
+
+    /// Create a new webdriver session on the specified server,
+    /// sending all requests through `transport` and writing them to
+    /// the cassette of `recorder`, see the `cassette` module.
+    pub async fn new_with_recorder(
+        webdriver_url: &str,
+        user_agent: Option<String>,
+        capabilities: Capabilities,
+        transport: Arc<dyn Transport>,
+        recorder: Recorder,
+    ) -> Result<Self> {
+        Self::connect(
+            webdriver_url,
+            user_agent,
+            capabilities,
+            transport,
+            Some(recorder),
+        )
+        .await
+    }
+
+    async fn connect(
+        webdriver_url: &str,
+        user_agent: Option<String>,
+        capabilities: Capabilities,
+        transport: Arc<dyn Transport>,
+        recorder: Option<Recorder>,
+    ) -> Result<Self> {
+        let client =
+            Client::new(webdriver_url, user_agent, capabilities, transport, recorder)
+                .await?;
+        // pin the initial window, so we can switch back to it
+        let window = match client.issue_cmd(&WebDriverCommand::GetWindowHandle).await? {
+            Value::String(h) => Some(h),
+            v => bail!(ErrorKind::NotW3C(v)),
+        };
+        let context = Context {
+            window,
+            frames: vec![],
+        };
+        Ok(Driver {
+            inner: Arc::new(Inner {
+                client,
+                current: tokio::sync::Mutex::new(Some(context.clone())),
+            }),
+            context: std::sync::Mutex::new(context),
+        })
+    }
+
+    /// Wait for `duration`, using the transport's timer
+    pub(crate) async fn sleep(&self, duration: Duration) {
+        self.inner.client.sleep(duration).await
+    }
+
+    /// Issue `cmd` in this handle's browsing context.
+    async fn issue_cmd(&self, cmd: &protocol::Cmd) -> Result<Value> {
+        let client = &self.inner.client;
+        let mut current = self.inner.current.lock().await;
+        let mine = self.context.lock().unwrap().clone();
+        // switching window replaces the whole context, and the
+        // handle's old window may have been closed
+        let replaces = matches!(cmd, WebDriverCommand::SwitchToWindow(..));
+        if !replaces && current.as_ref() != Some(&mine) {
+            // if restoring fails part way we don't know where we are
+            *current = None;
+            if let Some(ref window) = mine.window {
+                let p = SwitchToWindowParameters {
+                    handle: window.clone(),
+                };
+                client.issue_cmd(&WebDriverCommand::SwitchToWindow(p)).await?;
+            }
+            let top = SwitchToFrameParameters { id: None };
+            client.issue_cmd(&WebDriverCommand::SwitchToFrame(top)).await?;
+            for frame in &mine.frames {
+                let p = SwitchToFrameParameters {
+                    id: Some(FrameId::Element(frame.clone())),
+                };
+                client.issue_cmd(&WebDriverCommand::SwitchToFrame(p)).await?;
+            }
+            *current = Some(mine.clone());
+        }
+        let res = client.issue_cmd(cmd).await?;
+        let mut mine = mine;
+        mine.update(cmd);
+        if let WebDriverCommand::CloseWindow = cmd {
+            // the session has no current window until we switch to one
+            *current = None;
+        } else {
+            *current = Some(mine.clone());
+        }
+        *self.context.lock().unwrap() = mine;
+        Ok(res)
+    }
+
+    /// The number of handles to the session, this one and its clones
+    pub(crate) fn handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    /// Close the session, waiting for the server to respond. If other
+    /// clones of this handle exist only this handle is dropped, and
+    /// the session is closed with the last of them.
+    ///
+    /// Dropping the last handle also closes the session, but in the
+    /// background, spawned with `Transport::spawn`.
+    pub async fn quit(self) -> Result<()> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(inner) => inner.client.close().await,
+            Err(_) => Ok(()),
+        }
     }
 
     /// Navigate directly to the given URL.
@@ -86,13 +565,45 @@ impl Driver {
         let cmd = WebDriverCommand::Get(webdriver::command::GetParameters {
             url: self.current_url().await?.join(url)?.into(),
         });
-        self.0.issue_cmd(&cmd).await?;
+        self.issue_cmd(&cmd).await?;
         Ok(())
     }
 
+    /// Navigate to the given URL, wait for the document to reach
+    /// `state`, and return the navigation timing of the page load.
+    pub async fn goto_and_wait(
+        &self,
+        url: &str,
+        state: ReadyState,
+    ) -> Result<NavigationTiming> {
+        self.goto(url).await?;
+        self.wait_for_ready_state(state).await?;
+        let js = r#"
+            var e = performance.getEntriesByType('navigation');
+            return e.length > 0 ? e[0].toJSON() : null;
+        "#;
+        match self.execute(js.to_string(), vec![]).await? {
+            Value::Null => bail!("no navigation timing entry available"),
+            v => Ok(serde_json::from_value(v)?),
+        }
+    }
+
+    /// Wait for the `document.readyState` of the current page to
+    /// reach at least `state`.
+    pub async fn wait_for_ready_state(&self, state: ReadyState) -> Result<()> {
+        let js = "return document.readyState".to_string();
+        loop {
+            let current: ReadyState = self.execute_typed(js.clone(), ()).await?;
+            if current >= state {
+                break Ok(());
+            }
+            self.sleep(Duration::from_millis(100)).await
+        }
+    }
+
     /// Retrieve the currently active URL for this session.
     pub async fn current_url(&self) -> Result<url::Url> {
-        match self.0.issue_cmd(&WebDriverCommand::GetCurrentUrl).await?.as_str() {
+        match self.issue_cmd(&WebDriverCommand::GetCurrentUrl).await?.as_str() {
             Some(url) => Ok(url.parse()?),
             None => bail!(ErrorKind::NotW3C(Value::Null)),
         }
@@ -100,7 +611,7 @@ impl Driver {
 
     /// Get the HTML source for the current page.
     pub async fn source(&self) -> Result<String> {
-        match self.0.issue_cmd(&WebDriverCommand::GetPageSource).await?.as_str() {
+        match self.issue_cmd(&WebDriverCommand::GetPageSource).await?.as_str() {
             Some(src) => Ok(src.to_string()),
             None => bail!(ErrorKind::NotW3C(Value::Null)),
         }
@@ -108,13 +619,27 @@ impl Driver {
 
     /// Go back to the previous page.
     pub async fn back(&self) -> Result<()> {
-        self.0.issue_cmd(&WebDriverCommand::GoBack).await?;
+        self.issue_cmd(&WebDriverCommand::GoBack).await?;
+        Ok(())
+    }
+
+    /// Go forward to the next page.
+    pub async fn forward(&self) -> Result<()> {
+        self.issue_cmd(&WebDriverCommand::GoForward).await?;
         Ok(())
     }
 
+    /// Get the title of the current page.
+    pub async fn title(&self) -> Result<String> {
+        match self.issue_cmd(&WebDriverCommand::GetTitle).await? {
+            Value::String(title) => Ok(title),
+            v => bail!(ErrorKind::NotW3C(v)),
+        }
+    }
+
     /// Refresh the current previous page.
     pub async fn refresh(&self) -> Result<()> {
-        self.0.issue_cmd(&WebDriverCommand::Refresh).await?;
+        self.issue_cmd(&WebDriverCommand::Refresh).await?;
         Ok(())
     }
 
@@ -124,13 +649,13 @@ impl Driver {
             id: Some(FrameId::Element(frame)),
         };
         let cmd = WebDriverCommand::SwitchToFrame(p);
-        self.0.issue_cmd(&cmd).await?;
+        self.issue_cmd(&cmd).await?;
         Ok(())
     }
 
     /// Switch the focus to this frame's parent frame
     pub async fn switch_to_parent_frame(&self) -> Result<()> {
-        self.0.issue_cmd(&WebDriverCommand::SwitchToParentFrame).await?;
+        self.issue_cmd(&WebDriverCommand::SwitchToParentFrame).await?;
         Ok(())
     }
 
@@ -138,10 +663,47 @@ impl Driver {
     pub async fn switch_to_window(&self, window: String) -> Result<()> {
         let p = SwitchToWindowParameters { handle: window };
         let cmd = WebDriverCommand::SwitchToWindow(p);
-        self.0.issue_cmd(&cmd).await?;
+        self.issue_cmd(&cmd).await?;
         Ok(())
     }
 
+    /// Get the handle of the current window.
+    pub async fn window_handle(&self) -> Result<String> {
+        match self.issue_cmd(&WebDriverCommand::GetWindowHandle).await? {
+            Value::String(h) => Ok(h),
+            v => bail!(ErrorKind::NotW3C(v)),
+        }
+    }
+
+    /// Get the handles of all the windows (and tabs) of this session.
+    pub async fn window_handles(&self) -> Result<Vec<String>> {
+        let res = self.issue_cmd(&WebDriverCommand::GetWindowHandles).await?;
+        match serde_json::from_value(res.clone()) {
+            Ok(handles) => Ok(handles),
+            Err(_) => bail!(ErrorKind::NotW3C(res)),
+        }
+    }
+
+    /// Close the current window. Focus must be switched to another
+    /// window with `switch_to_window` before issuing further commands.
+    pub async fn close_window(&self) -> Result<()> {
+        self.issue_cmd(&WebDriverCommand::CloseWindow).await?;
+        Ok(())
+    }
+
+    /// Delete all the cookies visible to the current page.
+    pub async fn delete_all_cookies(&self) -> Result<()> {
+        self.issue_cmd(&WebDriverCommand::DeleteCookies).await?;
+        Ok(())
+    }
+
+    /// Ask the webdriver server whether it is ready to create new
+    /// sessions.
+    pub async fn status(&self) -> Result<ServerStatus> {
+        let res = self.issue_cmd(&WebDriverCommand::Status).await?;
+        Ok(serde_json::from_value(res)?)
+    }
+
     /// Execute the given JavaScript `script` in the current browser session.
     ///
     /// `args` is available to the script inside the `arguments`
@@ -151,11 +713,82 @@ impl Driver {
     pub async fn execute(&self, script: String, mut args: Vec<Value>) -> Result<Value> {
         self.fixup_elements(&mut args);
         let cmd = webdriver::command::JavascriptCommandParameters {
-            script: script,
+            script,
             args: Some(args),
         };
         let cmd = WebDriverCommand::ExecuteScript(cmd);
-        self.0.issue_cmd(&cmd).await
+        self.issue_cmd(&cmd).await
+    }
+
+    /// Execute the given JavaScript `script` in the current browser
+    /// session with typed arguments and return value.
+    ///
+    /// `args` must serialize to a JSON array, e.g. a tuple, `Vec`, or
+    /// array, each element of which becomes one entry of the
+    /// `arguments` array. `WebElement`s may appear anywhere in `args`
+    /// and in the return value, including inside nested arrays and
+    /// objects, and will be translated to and from DOM elements.
+    ///
+    /// ```text
+    /// let (title, links): (String, Vec<WebElement>) = driver
+    ///     .execute_typed(
+    ///         "return [document.title, arguments[0].querySelectorAll('a')]".into(),
+    ///         (body,),
+    ///     )
+    ///     .await?;
+    /// ```
+    pub async fn execute_typed<A, R>(&self, script: String, args: A) -> Result<R>
+    where
+        A: Serialize,
+        R: DeserializeOwned,
+    {
+        let args = match serde_json::to_value(args)? {
+            Value::Array(a) => a,
+            Value::Null => vec![],
+            v => bail!("script arguments must serialize to an array, got {}", v),
+        };
+        let mut res = self.execute(script, args).await?;
+        self.unfixup_elements(&mut res);
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Execute the given asynchronous JavaScript `script` in the
+    /// current browser session.
+    ///
+    /// As with `execute`, `args` is available to the script inside
+    /// the `arguments` array. In addition the last element of
+    /// `arguments` is a callback, the script signals completion by
+    /// calling it, and the value passed to the callback is
+    /// returned. This allows the script to wait for promises,
+    /// fetches, or timers before returning, e.g.
+    ///
+    /// ```text
+    /// var done = arguments[arguments.length - 1];
+    /// fetch(arguments[0]).then(r => r.text()).then(done);
+    /// ```
+    pub async fn execute_async(
+        &self,
+        script: String,
+        mut args: Vec<Value>,
+    ) -> Result<Value> {
+        self.fixup_elements(&mut args);
+        let cmd = webdriver::command::JavascriptCommandParameters {
+            script,
+            args: Some(args),
+        };
+        let cmd = WebDriverCommand::ExecuteAsyncScript(cmd);
+        self.issue_cmd(&cmd).await
+    }
+
+    /// Print the current page to PDF, and return the PDF document.
+    pub async fn print_pdf(&self, options: PrintOptions) -> Result<Vec<u8>> {
+        let cmd = WebDriverCommand::Print(options.into());
+        match self.issue_cmd(&cmd).await? {
+            Value::String(pdf) => {
+                Ok(base64::engine::general_purpose::STANDARD.decode(pdf)?)
+            }
+            v => bail!(ErrorKind::NotW3C(v)),
+        }
     }
 
     /// Wait for the page to navigate to a new URL before proceeding.
@@ -173,7 +806,7 @@ impl Driver {
             if self.current_url().await? != current {
                 break Ok(());
             }
-            sleep(Duration::from_millis(100)).await
+            self.sleep(Duration::from_millis(100)).await
         }
     }
 
@@ -190,8 +823,8 @@ impl Driver {
                 WebDriverCommand::FindElementElement(elt, locator.into())
             }
         };
-        let res = self.0.issue_cmd(&cmd).await?;
-        Ok(self.parse_lookup(res)?)
+        let res = self.issue_cmd(&cmd).await?;
+        self.parse_lookup(res)
     }
 
     pub async fn find_all(
@@ -205,7 +838,7 @@ impl Driver {
                 WebDriverCommand::FindElementElements(elt, locator.into())
             }
         };
-        match self.0.issue_cmd(&cmd).await? {
+        match self.issue_cmd(&cmd).await? {
             Value::Array(a) => Ok(a
                 .into_iter()
                 .map(|e| self.parse_lookup(e))
@@ -217,13 +850,88 @@ impl Driver {
     generate_wait_for_find!(wait_for_find, find, WebElement);
     generate_wait_for_find!(wait_for_find_all, find_all, Vec<WebElement>);
 
+    /// Get the element that currently has focus.
+    pub async fn active_element(&self) -> Result<WebElement> {
+        let res = self.issue_cmd(&WebDriverCommand::GetActiveElement).await?;
+        self.parse_lookup(res)
+    }
+
+    /// Give the keyboard focus to this element.
+    pub async fn focus(&self, eid: WebElement) -> Result<()> {
+        let args = vec![serde_json::to_value(eid)?];
+        let js = "arguments[0].focus()".to_string();
+        self.execute(js, args).await?;
+        Ok(())
+    }
+
+    /// Press and release each of the given keys in order, sending
+    /// them to whichever element currently has focus. Special keys
+    /// are given by their WebDriver code points, e.g. `"\u{E004}"`
+    /// for Tab.
+    pub async fn press_keys(&self, keys: &str) -> Result<()> {
+        use webdriver::actions::{
+            ActionSequence, ActionsType, KeyAction, KeyActionItem, KeyDownAction,
+            KeyUpAction,
+        };
+        let mut actions = Vec::new();
+        for k in keys.chars() {
+            let value = k.to_string();
+            actions.push(KeyActionItem::Key(KeyAction::Down(KeyDownAction {
+                value: value.clone(),
+            })));
+            actions.push(KeyActionItem::Key(KeyAction::Up(KeyUpAction { value })));
+        }
+        let p = webdriver::command::ActionsParameters {
+            actions: vec![ActionSequence {
+                id: "keyboard".into(),
+                actions: ActionsType::Key { actions },
+            }],
+        };
+        self.issue_cmd(&WebDriverCommand::PerformActions(p)).await?;
+        self.issue_cmd(&WebDriverCommand::ReleaseActions).await?;
+        Ok(())
+    }
+
+    /// Press Tab repeatedly, starting from the currently focused
+    /// element, and return the elements that received focus in
+    /// order. Stops when focus returns to an element that was already
+    /// visited (the tab order wrapped around), or after `max` presses.
+    pub async fn tab_order(&self, max: usize) -> Result<Vec<WebElement>> {
+        let mut order: Vec<WebElement> = Vec::new();
+        for _ in 0..max {
+            self.press_keys("\u{E004}").await?;
+            let elt = self.active_element().await?;
+            if order.contains(&elt) {
+                break;
+            }
+            order.push(elt);
+        }
+        Ok(order)
+    }
+
+    /// Get the shadow root attached to the element `eid`.
+    pub async fn shadow_root(&self, eid: WebElement) -> Result<ShadowRoot<'_>> {
+        let cmd = WebDriverCommand::Extension(ExtensionCommand::GetShadowRoot(eid));
+        let res = self.issue_cmd(&cmd).await?;
+        Ok(ShadowRoot {
+            driver: self,
+            id: self.parse_reference(res, SHADOW_ROOT_KEY)?,
+        })
+    }
+
     /// Extract the `WebElement` from a `FindElement` or `FindElementElement` command.
-    fn parse_lookup(&self, mut res: Value) -> Result<WebElement> {
-        let key = if self.0.legacy {
+    fn parse_lookup(&self, res: Value) -> Result<WebElement> {
+        let key = if self.inner.client.legacy {
             "ELEMENT"
         } else {
             ELEMENT_KEY
         };
+        Ok(WebElement(self.parse_reference(res, key)?))
+    }
+
+    /// Extract the id of a remote object reference, e.g. an element
+    /// or shadow root, identified by `key`.
+    fn parse_reference(&self, mut res: Value, key: &str) -> Result<String> {
         let o = {
             if let Some(o) = res.as_object_mut() {
                 o
@@ -233,7 +941,7 @@ impl Driver {
         };
         match o.remove(key) {
             None => bail!(ErrorKind::NotW3C(res)),
-            Some(Value::String(wei)) => Ok(webdriver::common::WebElement(wei)),
+            Some(Value::String(id)) => Ok(id),
             Some(v) => {
                 o.insert(key.to_string(), v);
                 bail!(ErrorKind::NotW3C(res))
@@ -242,19 +950,55 @@ impl Driver {
     }
 
     fn fixup_elements(&self, args: &mut [Value]) {
-        if self.0.legacy {
+        if self.inner.client.legacy {
             for arg in args {
                 // the serialization of WebElement uses the W3C index,
                 // but legacy implementations need us to use the "ELEMENT" index
-                if let Value::Object(ref mut o) = *arg {
-                    if let Some(wei) = o.remove(ELEMENT_KEY) {
-                        o.insert("ELEMENT".to_string(), wei);
+                match *arg {
+                    Value::Object(ref mut o) => {
+                        if let Some(wei) = o.remove(ELEMENT_KEY) {
+                            o.insert("ELEMENT".to_string(), wei);
+                        } else {
+                            for v in o.values_mut() {
+                                self.fixup_elements(std::slice::from_mut(v))
+                            }
+                        }
                     }
+                    Value::Array(ref mut a) => self.fixup_elements(a),
+                    _ => (),
                 }
             }
         }
     }
 
+    /// The reverse of `fixup_elements`, rewrite element references
+    /// returned by a legacy implementation to use the W3C index so
+    /// they will deserialize as `WebElement`s.
+    fn unfixup_elements(&self, res: &mut Value) {
+        if self.inner.client.legacy {
+            match *res {
+                Value::Object(ref mut o) => {
+                    let is_elt = o.len() == 1
+                        && o.get("ELEMENT").map(|v| v.is_string()).unwrap_or(false);
+                    if is_elt {
+                        let wei = o.remove("ELEMENT").unwrap();
+                        o.insert(ELEMENT_KEY.to_string(), wei);
+                    } else {
+                        for v in o.values_mut() {
+                            self.unfixup_elements(v)
+                        }
+                    }
+                }
+                Value::Array(ref mut a) => {
+                    for v in a {
+                        self.unfixup_elements(v)
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
     /// Look up an attribute value for this element by name.
     pub async fn attr(
         &self,
@@ -262,7 +1006,7 @@ impl Driver {
         attribute: String,
     ) -> Result<Option<String>> {
         let cmd = WebDriverCommand::GetElementAttribute(eid, attribute);
-        match self.0.issue_cmd(&cmd).await? {
+        match self.issue_cmd(&cmd).await? {
             Value::String(v) => Ok(Some(v)),
             Value::Null => Ok(None),
             v => bail!(ErrorKind::NotW3C(v)),
@@ -272,7 +1016,7 @@ impl Driver {
     /// Look up a DOM property for this element by name.
     pub async fn prop(&self, eid: WebElement, prop: String) -> Result<Option<String>> {
         let cmd = WebDriverCommand::GetElementProperty(eid, prop);
-        match self.0.issue_cmd(&cmd).await? {
+        match self.issue_cmd(&cmd).await? {
             Value::String(v) => Ok(Some(v)),
             Value::Null => Ok(None),
             v => bail!(ErrorKind::NotW3C(v)),
@@ -282,7 +1026,7 @@ impl Driver {
     /// Retrieve the text contents of this elment.
     pub async fn text(&self, eid: WebElement) -> Result<String> {
         let cmd = WebDriverCommand::GetElementText(eid);
-        match self.0.issue_cmd(&cmd).await? {
+        match self.issue_cmd(&cmd).await? {
             Value::String(v) => Ok(v),
             v => bail!(ErrorKind::NotW3C(v)),
         }
@@ -297,10 +1041,80 @@ impl Driver {
             .ok_or_else(|| Error::from(ErrorKind::NotW3C(Value::Null)))
     }
 
+    /// Get the computed WAI-ARIA role of this element.
+    pub async fn computed_role(&self, eid: WebElement) -> Result<String> {
+        let cmd = WebDriverCommand::Extension(ExtensionCommand::GetComputedRole(eid));
+        match self.issue_cmd(&cmd).await? {
+            Value::String(v) => Ok(v),
+            v => bail!(ErrorKind::NotW3C(v)),
+        }
+    }
+
+    /// Get the computed accessible name (label) of this element.
+    pub async fn computed_label(&self, eid: WebElement) -> Result<String> {
+        let cmd = WebDriverCommand::Extension(ExtensionCommand::GetComputedLabel(eid));
+        match self.issue_cmd(&cmd).await? {
+            Value::String(v) => Ok(v),
+            v => bail!(ErrorKind::NotW3C(v)),
+        }
+    }
+
+    /// Find all the elements with the computed role `role`, and, if
+    /// `name` is given, the accessible name `name` (compared with
+    /// whitespace normalized), e.g. `find_all_by_role("button",
+    /// Some("Save"), None)`. This is the user facing way to select
+    /// elements, it does not depend on the structure of the markup.
+    ///
+    /// Candidates are narrowed with a CSS selector for well known
+    /// roles, other roles check every element under `root`, which can
+    /// be slow on large pages.
+    pub async fn find_all_by_role(
+        &self,
+        role: &str,
+        name: Option<&str>,
+        root: Option<WebElement>,
+    ) -> Result<Vec<WebElement>> {
+        let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+        let name = name.map(normalize);
+        let mut res = Vec::new();
+        for elt in self.find_all(role_candidates(role), root).await? {
+            if self.computed_role(elt.clone()).await? != role {
+                continue;
+            }
+            if let Some(ref name) = name {
+                if normalize(&self.computed_label(elt.clone()).await?) != *name {
+                    continue;
+                }
+            }
+            res.push(elt)
+        }
+        Ok(res)
+    }
+
+    /// Find the first element with the computed role `role` and
+    /// optionally the accessible name `name`. See `find_all_by_role`.
+    pub async fn find_by_role(
+        &self,
+        role: &str,
+        name: Option<&str>,
+        root: Option<WebElement>,
+    ) -> Result<WebElement> {
+        match self.find_all_by_role(role, name, root).await?.into_iter().next() {
+            Some(elt) => Ok(elt),
+            None => {
+                let msg = format!("no element with role {} and name {:?}", role, name);
+                bail!(ErrorKind::WebDriver(WebDriverError::new(
+                    ErrorStatus::NoSuchElement,
+                    msg
+                )))
+            }
+        }
+    }
+
     /// Click on this element
     pub async fn click(&self, eid: WebElement) -> Result<()> {
         let cmd = WebDriverCommand::ElementClick(eid);
-        let r = self.0.issue_cmd(&cmd).await?;
+        let r = self.issue_cmd(&cmd).await?;
         if r.is_null() || r.as_object().map(|o| o.is_empty()).unwrap_or(false) {
             // geckodriver returns {} :(
             Ok(())
@@ -313,14 +1127,14 @@ impl Driver {
     pub async fn scroll_into_view(&self, eid: WebElement) -> Result<()> {
         let args = vec![serde_json::to_value(eid)?];
         let js = "arguments[0].scrollIntoView(true)".to_string();
-        self.clone().execute(js, args).await?;
+        self.execute(js, args).await?;
         Ok(())
     }
 
     /// Follow the `href` target of the element matching the given CSS
     /// selector *without* causing a click interaction.
     pub async fn follow(&self, eid: WebElement) -> Result<()> {
-        match self.clone().attr(eid.clone(), String::from("href")).await? {
+        match self.attr(eid.clone(), String::from("href")).await? {
             None => bail!("no href attribute"),
             Some(href) => {
                 let current = self.current_url().await?.join(&href)?;
@@ -336,15 +1150,15 @@ impl Driver {
         name: String,
         value: String,
     ) -> Result<()> {
-        let locator = Locator::Css(format!("input[name='{}']", name));
-        let elt = self.clone().find(locator.into(), Some(eid)).await?;
+        let locator = selector::Css::tag("input").attr_eq("name", &name).into();
+        let elt = self.find(locator, Some(eid)).await?;
         let args = {
             let mut a = vec![serde_json::to_value(elt)?, Value::String(value)];
             self.fixup_elements(&mut a);
             a
         };
         let js = "arguments[0].value = arguments[1]".to_string();
-        let res = self.clone().execute(js, args).await?;
+        let res = self.execute(js, args).await?;
         if res.is_null() {
             Ok(())
         } else {
@@ -360,20 +1174,19 @@ impl Driver {
 
     /// Submit the form `eid` using the button matched by the given selector.
     pub async fn submit_with(&self, eid: WebElement, button: Locator) -> Result<()> {
-        let elt = self.clone().find(button.into(), Some(eid)).await?;
-        Ok(self.clone().click(elt).await?)
+        let elt = self.find(button, Some(eid)).await?;
+        self.click(elt).await
     }
 
     /// Submit this form using the form submit button with the given
     /// label (case-insensitive).
     pub async fn submit_using(&self, eid: WebElement, button_label: String) -> Result<()> {
-        let escaped = button_label.replace('\\', "\\\\").replace('"', "\\\"");
-        let btn = format!(
-            "input[type=submit][value=\"{}\" i],\
-             button[type=submit][value=\"{}\" i]",
-            escaped, escaped
-        );
-        Ok(self.submit_with(eid, Locator::Css(btn)).await?)
+        let btn = |tag| {
+            selector::Css::tag(tag)
+                .attr_eq("type", "submit")
+                .attr_eq_i("value", &button_label)
+        };
+        self.submit_with(eid, btn("input").or(btn("button")).into()).await
     }
 
     /// Submit this form directly, without clicking any buttons.
@@ -400,7 +1213,7 @@ impl Driver {
             self.fixup_elements(&mut a);
             a
         };
-        self.clone().execute(js, args).await?;
+        self.execute(js, args).await?;
         Ok(())
     }
 
@@ -0,0 +1,414 @@
+//! A pool of browser sessions for parallel work.
+//!
+//! Sessions are created as they are needed, up to the pool's maximum
+//! size and the concurrency limit of each webdriver endpoint, and a
+//! minimum number of idle sessions can be kept ready. Idle sessions
+//! are health checked before being handed out, reset when they are
+//! returned, and closed after a configurable number of uses.
+//!
+//! The reset closes extra windows, navigates to a blank page, and
+//! clears the cookies and storage of the origin the session was left
+//! on. Webdriver can't reach the cookies and storage of other origins
+//! the session visited, so they can leak into the next checkout. Use
+//! `max_uses(1)` if each checkout needs a fresh browser profile.
+//!
+//! ```text
+//! let pool = DriverPool::builder()
+//!     .endpoint("http://localhost:4444", 4)
+//!     .max_uses(100)
+//!     .build();
+//! let driver = pool.get().await?;
+//! driver.goto("https://example.com").await?;
+//! driver.release().await;
+//! ```
+
+use crate::{error::*, transport::Transport, Driver};
+use futures::future::select_all;
+use std::{
+    cmp::Reverse,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use webdriver::capabilities::Capabilities;
+
+/// The endpoint's and the pool's permits held by a checked out session
+type Permits = Option<(OwnedSemaphorePermit, OwnedSemaphorePermit)>;
+
+struct Idle {
+    driver: Driver,
+    uses: usize,
+}
+
+struct Endpoint {
+    url: String,
+    permits: Arc<Semaphore>,
+    idle: Mutex<Vec<Idle>>,
+}
+
+struct Inner {
+    endpoints: Vec<Endpoint>,
+    permits: Arc<Semaphore>,
+    min_size: usize,
+    max_uses: Option<usize>,
+    user_agent: Option<String>,
+    capabilities: Capabilities,
+    transport: Option<Arc<dyn Transport>>,
+    /// Whether sessions are being created to reach `min_size`
+    filling: AtomicBool,
+}
+
+/// Builds a `DriverPool`.
+pub struct DriverPoolBuilder {
+    endpoints: Vec<(String, usize)>,
+    min_size: usize,
+    max_size: Option<usize>,
+    max_uses: Option<usize>,
+    user_agent: Option<String>,
+    capabilities: Capabilities,
+    transport: Option<Arc<dyn Transport>>,
+}
+
+impl DriverPoolBuilder {
+    /// Add a webdriver server, on which at most `max_sessions`
+    /// sessions will be open at once.
+    pub fn endpoint(mut self, webdriver_url: &str, max_sessions: usize) -> Self {
+        self.endpoints.push((webdriver_url.into(), max_sessions));
+        self
+    }
+
+    /// The number of idle sessions to keep ready. `DriverPool::warm_up`
+    /// creates them ahead of time, and the pool creates replacements
+    /// in the background as sessions are checked out or closed, as far
+    /// as the size limits allow.
+    pub fn min_size(mut self, n: usize) -> Self {
+        self.min_size = n;
+        self
+    }
+
+    /// The maximum number of sessions open at once across all
+    /// endpoints. Defaults to the sum of the endpoint limits.
+    pub fn max_size(mut self, n: usize) -> Self {
+        self.max_size = Some(n);
+        self
+    }
+
+    /// Close a session after it has been checked out `n` times,
+    /// instead of returning it to the pool.
+    pub fn max_uses(mut self, n: usize) -> Self {
+        self.max_uses = Some(n);
+        self
+    }
+
+    /// The user agent to create sessions with, see `Driver::new`
+    pub fn user_agent(mut self, user_agent: String) -> Self {
+        self.user_agent = Some(user_agent);
+        self
+    }
+
+    /// Additional capabilities to create sessions with, see
+    /// `Driver::new_with_capabilities`
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The transport to create sessions with, see
+    /// `Driver::new_with_transport`. Defaults to the transport
+    /// selected by the enabled cargo features.
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    pub fn build(self) -> DriverPool {
+        let total = self.endpoints.iter().map(|(_, n)| n).sum();
+        let endpoints = self
+            .endpoints
+            .into_iter()
+            .map(|(url, n)| Endpoint {
+                url,
+                permits: Arc::new(Semaphore::new(n)),
+                idle: Mutex::new(Vec::new()),
+            })
+            .collect();
+        let max_size = self.max_size.unwrap_or(total).min(total);
+        DriverPool(Arc::new(Inner {
+            endpoints,
+            permits: Arc::new(Semaphore::new(max_size)),
+            min_size: self.min_size.min(max_size),
+            max_uses: self.max_uses,
+            user_agent: self.user_agent,
+            capabilities: self.capabilities,
+            transport: self.transport,
+            filling: AtomicBool::new(false),
+        }))
+    }
+}
+
+/// A pool of webdriver sessions. Cloning the pool is cheap, and all
+/// clones share the same sessions.
+#[derive(Clone)]
+pub struct DriverPool(Arc<Inner>);
+
+impl DriverPool {
+    pub fn builder() -> DriverPoolBuilder {
+        DriverPoolBuilder {
+            endpoints: Vec::new(),
+            min_size: 0,
+            max_size: None,
+            max_uses: None,
+            user_agent: None,
+            capabilities: Capabilities::new(),
+            transport: None,
+        }
+    }
+
+    /// Create sessions until `min_size` sessions are idle in the pool,
+    /// or the pool is at its maximum size.
+    pub async fn warm_up(&self) -> Result<()> {
+        if self.0.filling.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let res = self.fill().await;
+        self.0.filling.store(false, Ordering::Release);
+        res
+    }
+
+    async fn fill(&self) -> Result<()> {
+        // idle sessions don't hold permits, but count against the limits
+        let room = |e: &Endpoint| {
+            let idle = e.idle.lock().unwrap().len();
+            e.permits.available_permits().saturating_sub(idle)
+        };
+        while self.idle() < self.0.min_size {
+            if self.0.permits.available_permits() <= self.idle() {
+                break;
+            }
+            let endpoint = self
+                .0
+                .endpoints
+                .iter()
+                .enumerate()
+                .max_by_key(|(i, e)| (room(e), Reverse(*i)))
+                .filter(|(_, e)| room(e) > 0);
+            let (endpoint, permit, pool_permit) = match endpoint {
+                None => break,
+                Some((i, e)) => match (
+                    e.permits.clone().try_acquire_owned(),
+                    self.0.permits.clone().try_acquire_owned(),
+                ) {
+                    (Ok(p), Ok(pp)) => (i, p, pp),
+                    _ => break,
+                },
+            };
+            let driver = self.connect(endpoint).await?;
+            let idle = Idle { driver, uses: 0 };
+            self.0.endpoints[endpoint].idle.lock().unwrap().push(idle);
+            drop((permit, pool_permit))
+        }
+        Ok(())
+    }
+
+    /// Create sessions in the background to get back to `min_size`
+    /// idle sessions.
+    fn replenish(&self) {
+        if self.0.min_size == 0 || self.idle() >= self.0.min_size {
+            return;
+        }
+        if let Ok(transport) = self.transport() {
+            let pool = self.clone();
+            transport.spawn(Box::pin(async move {
+                let _ = pool.warm_up().await;
+            }));
+        }
+    }
+
+    fn transport(&self) -> Result<Arc<dyn Transport>> {
+        match self.0.transport {
+            Some(ref t) => Ok(t.clone()),
+            None => crate::transport::default_transport(),
+        }
+    }
+
+    /// The number of idle sessions on all the endpoints
+    fn idle(&self) -> usize {
+        self.0.endpoints.iter().map(|e| e.idle.lock().unwrap().len()).sum()
+    }
+
+    async fn connect(&self, endpoint: usize) -> Result<Driver> {
+        let transport = self.transport()?;
+        Driver::new_with_transport(
+            &self.0.endpoints[endpoint].url,
+            self.0.user_agent.clone(),
+            self.0.capabilities.clone(),
+            transport,
+        )
+        .await
+    }
+
+    /// Wait for a permit on any endpoint, preferring endpoints with
+    /// idle sessions.
+    async fn acquire_endpoint(&self) -> Result<(usize, OwnedSemaphorePermit)> {
+        if self.0.endpoints.is_empty() {
+            bail!("driver pool has no endpoints")
+        }
+        let has_idle = |e: &Endpoint| !e.idle.lock().unwrap().is_empty();
+        let order = (0..self.0.endpoints.len())
+            .filter(|i| has_idle(&self.0.endpoints[*i]))
+            .chain((0..self.0.endpoints.len()).filter(|i| !has_idle(&self.0.endpoints[*i])));
+        for i in order {
+            if let Ok(p) = self.0.endpoints[i].permits.clone().try_acquire_owned() {
+                return Ok((i, p));
+            }
+        }
+        let waits = self
+            .0
+            .endpoints
+            .iter()
+            .map(|e| Box::pin(e.permits.clone().acquire_owned()));
+        let (p, i, _) = select_all(waits).await;
+        Ok((i, p.map_err(|_| Error::from("driver pool closed"))?))
+    }
+
+    /// Check out a session, creating one if no healthy idle session
+    /// is available. Waits if the pool, or every endpoint, is at its
+    /// concurrency limit.
+    pub async fn get(&self) -> Result<PooledDriver> {
+        let pool_permit = self
+            .0
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| Error::from("driver pool closed"))?;
+        let (endpoint, permit) = self.acquire_endpoint().await?;
+        let idle = loop {
+            let idle = self.0.endpoints[endpoint].idle.lock().unwrap().pop();
+            match idle {
+                Some(idle) if healthy(&idle.driver).await => break Some(idle),
+                // dropping the unhealthy driver closes its session
+                Some(_) => (),
+                None => break None,
+            }
+        };
+        let (driver, uses) = match idle {
+            Some(idle) => (idle.driver, idle.uses + 1),
+            None => (self.connect(endpoint).await?, 1),
+        };
+        self.replenish();
+        Ok(PooledDriver {
+            driver: Some(driver),
+            uses,
+            endpoint,
+            pool: self.clone(),
+            permits: Some((permit, pool_permit)),
+        })
+    }
+
+    /// Reset `driver` and return it to the pool, unless it is worn out,
+    /// the reset fails, or it is still used through clones, which
+    /// close the session when the last of them is dropped.
+    async fn check_in(
+        self,
+        endpoint: usize,
+        driver: Driver,
+        uses: usize,
+        permits: Permits,
+    ) {
+        let reuse = driver.handles() == 1
+            && self.0.max_uses.map(|max| uses < max).unwrap_or(true)
+            && reset(&driver).await.is_ok();
+        if reuse {
+            let idle = Idle { driver, uses };
+            self.0.endpoints[endpoint].idle.lock().unwrap().push(idle)
+        }
+        drop(permits);
+        self.replenish()
+    }
+}
+
+/// Is the server up, and the session still responding. The server's
+/// `ready` isn't checked, it only says whether the server can create
+/// more sessions, e.g. geckodriver isn't ready while a session is open.
+async fn healthy(driver: &Driver) -> bool {
+    driver.status().await.is_ok() && driver.current_url().await.is_ok()
+}
+
+/// Return the session to a clean state, as far as webdriver can: close
+/// all windows but one, clear the cookies and storage of the current
+/// origin, and navigate to a blank page.
+async fn reset(driver: &Driver) -> Result<()> {
+    let handles = driver.window_handles().await?;
+    if let Some((first, rest)) = handles.split_first() {
+        for h in rest {
+            driver.switch_to_window(h.clone()).await?;
+            driver.close_window().await?;
+        }
+        driver.switch_to_window(first.clone()).await?;
+    }
+    driver.delete_all_cookies().await?;
+    let js = "try { localStorage.clear(); sessionStorage.clear(); } catch (e) {}";
+    driver.execute(js.to_string(), vec![]).await?;
+    driver.goto("about:blank").await
+}
+
+/// A session checked out of a `DriverPool`. It derefs to `Driver`.
+///
+/// Use `release` to return the session to the pool. If it is just
+/// dropped, it is reset and returned to the pool in the background.
+/// A session that is still used through clones of the `Driver` when
+/// it is released isn't returned to the pool, it is closed when the
+/// last clone is dropped.
+pub struct PooledDriver {
+    driver: Option<Driver>,
+    uses: usize,
+    endpoint: usize,
+    pool: DriverPool,
+    permits: Permits,
+}
+
+impl PooledDriver {
+    /// Reset the session and return it to the pool. If the reset
+    /// fails the session is closed instead.
+    pub async fn release(mut self) {
+        let driver = self.driver.take().unwrap();
+        let permits = self.permits.take();
+        let pool = self.pool.clone();
+        pool.check_in(self.endpoint, driver, self.uses, permits).await
+    }
+
+    /// Close the session instead of returning it to the pool, e.g.
+    /// because it is in a bad state.
+    pub fn discard(mut self) {
+        self.driver.take();
+        self.permits.take();
+        self.pool.replenish();
+    }
+}
+
+impl Deref for PooledDriver {
+    type Target = Driver;
+
+    fn deref(&self) -> &Driver {
+        self.driver.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledDriver {
+    fn drop(&mut self) {
+        if let Some(driver) = self.driver.take() {
+            let pool = self.pool.clone();
+            let (endpoint, uses) = (self.endpoint, self.uses);
+            let permits = self.permits.take();
+            // if the transport can't spawn the check in, the session
+            // is dropped instead
+            if let Ok(transport) = pool.transport() {
+                transport.spawn(Box::pin(pool.check_in(endpoint, driver, uses, permits)));
+            }
+        }
+    }
+}
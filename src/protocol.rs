@@ -1,5 +1,5 @@
 //! This module handles the low level details of sending requests to
-//! the webdriver server using hyper, and interpreting errors. It also
+//! the webdriver server through a `Transport`, and interpreting errors. It also
 //! handles some of the inconsistancies in the implementation of the
 //! webdriver standard between different browsers.
 
@@ -8,28 +8,57 @@
 // in his library. Without that single repository of quirks this
 // library would have been much harder to write.
 
-use crate::error::*;
-use futures::prelude::*;
-use hyper::{self, Method};
-use hyper_tls;
+use crate::{
+    cassette::Recorder,
+    error::*,
+    transport::{Request, Response, Transport},
+};
+use futures::future::BoxFuture;
+use http::{Method, StatusCode};
 use serde_json::Value;
-use bytes::BytesMut;
-use std::str::from_utf8;
-use url;
+use std::{str::from_utf8, sync::Arc};
 use webdriver::{
     self,
-    command::WebDriverCommand,
-    common::{FrameId, ELEMENT_KEY},
+    command::{WebDriverCommand, WebDriverExtensionCommand},
+    common::{FrameId, WebElement, ELEMENT_KEY},
     error::{ErrorStatus, WebDriverError},
 };
 
-type Cmd = WebDriverCommand<webdriver::command::VoidWebDriverExtensionCommand>;
-type HttpClient =
-    hyper::Client<hyper_tls::HttpsConnector<hyper::client::HttpConnector>, hyper::Body>;
+/// Commands that are part of the W3C spec, or are common vendor
+/// extensions, but are not (yet) modeled by the webdriver crate.
+#[derive(Clone, Debug)]
+pub(crate) enum ExtensionCommand {
+    GetShadowRoot(WebElement),
+    /// shadow root id, locator parameters
+    FindShadowElement(String, Value),
+    /// shadow root id, locator parameters
+    FindShadowElements(String, Value),
+    GetComputedRole(WebElement),
+    GetComputedLabel(WebElement),
+    /// Selenium's file upload extension, the parameters are
+    /// `{"file": <base64 encoded zip>}`
+    UploadFile(Value),
+}
+
+impl WebDriverExtensionCommand for ExtensionCommand {
+    fn parameters_json(&self) -> Option<Value> {
+        match self {
+            ExtensionCommand::GetShadowRoot(..)
+            | ExtensionCommand::GetComputedRole(..)
+            | ExtensionCommand::GetComputedLabel(..) => None,
+            ExtensionCommand::FindShadowElement(_, ref loc)
+            | ExtensionCommand::FindShadowElements(_, ref loc) => Some(loc.clone()),
+            ExtensionCommand::UploadFile(ref file) => Some(file.clone()),
+        }
+    }
+}
+
+pub(crate) type Cmd = WebDriverCommand<ExtensionCommand>;
 
 /// A WebDriver client tied to a single browser session.
 pub(crate) struct Client {
-    http_client: HttpClient,
+    transport: Arc<dyn Transport>,
+    recorder: Option<Recorder>,
     webdriver_url: url::Url,
     user_agent: Option<String>,
     session_id: Option<String>,
@@ -38,33 +67,72 @@ pub(crate) struct Client {
 
 impl Drop for Client {
     fn drop(&mut self) {
-        let _ = self.shutdown();
+        // if the transport can't spawn the request, the session will
+        // be left for the server to time out. Use `close` to shut down
+        // deterministically.
+        if let Ok(Some(req)) = self.shutdown() {
+            let res = self.send(|| "DeleteSession".into(), req);
+            self.transport.spawn(Box::pin(async move {
+                let _ = res.await;
+            }));
+        }
     }
 }
 
 impl Client {
-    fn shutdown(&mut self) -> Result<()> {
-        match self.session_id {
-            None => Ok(()),
-            Some(ref s) => {
-                let url = self.webdriver_url.join(&format!("/session/{}", s))?;
-                self.session_id = None;
-                let req = hyper::Request::builder()
-                    .method(Method::DELETE)
-                    .uri(url.as_str())
-                    .body(hyper::Body::from(""))?;
-                let http = self.http_client.clone();
-                tokio::spawn(async move {
-                    let _ = http.request(req).await;
-                });
-                Ok(())
-            }
+    /// Build the request that deletes the session, if there is one
+    fn shutdown(&mut self) -> Result<Option<Request>> {
+        match self.session_id.take() {
+            None => Ok(None),
+            Some(s) => Ok(Some(Request {
+                method: Method::DELETE,
+                url: self.webdriver_url.join(&format!("/session/{}", s))?,
+                headers: vec![],
+                body: None,
+            })),
         }
     }
 
+    /// Send `req`, recording the exchange under the name returned by
+    /// `command` if a recorder is attached
+    fn send<F>(&self, command: F, req: Request) -> BoxFuture<'static, Result<Response>>
+    where
+        F: FnOnce() -> String,
+    {
+        let recorder = match self.recorder {
+            None => return self.transport.send(req),
+            Some(ref r) => r.clone(),
+        };
+        let res = self.transport.send(req.clone());
+        let command = command();
+        Box::pin(async move {
+            let res = res.await;
+            // a failed write is reported by `Recorder::check`, the
+            // command itself succeeded
+            recorder.record(&command, &req, &res);
+            res
+        })
+    }
+
+    /// Wait for `duration`, using the transport's timer
+    pub(crate) fn sleep(
+        &self,
+        duration: std::time::Duration,
+    ) -> impl std::future::Future<Output = ()> {
+        self.transport.sleep(duration)
+    }
+
+    /// Delete the session, and wait for the server to respond
+    pub(crate) async fn close(mut self) -> Result<()> {
+        if let Some(req) = self.shutdown()? {
+            self.send(|| "DeleteSession".into(), req).await?;
+        }
+        Ok(())
+    }
+
     fn decode_error(
         &self,
-        status: hyper::StatusCode,
+        status: StatusCode,
         legacy_status: u64,
         response: Value,
     ) -> Result<Error> {
@@ -104,69 +172,51 @@ impl Client {
                     _ => bail!(ErrorKind::NotW3C(Value::Object(response))),
                 }
             } else {
-                use hyper::StatusCode;
-                let error = match response["error"].as_str() {
+                let error = match response.get("error").and_then(|e| e.as_str()) {
                     None => bail!(ErrorKind::NotW3C(Value::Object(response))),
                     Some(e) => e,
                 };
-                if status == StatusCode::BAD_REQUEST {
-                    match error {
-                        "element click intercepted" => {
-                            ErrorStatus::ElementClickIntercepted
-                        }
-                        "element not selectable" => ErrorStatus::ElementNotSelectable,
-                        "element not interactable" => ErrorStatus::ElementNotInteractable,
-                        "insecure certificate" => ErrorStatus::InsecureCertificate,
-                        "invalid argument" => ErrorStatus::InvalidArgument,
-                        "invalid cookie domain" => ErrorStatus::InvalidCookieDomain,
-                        "invalid coordinates" => ErrorStatus::InvalidCoordinates,
-                        "invalid element state" => ErrorStatus::InvalidElementState,
-                        "invalid selector" => ErrorStatus::InvalidSelector,
-                        "no such alert" => ErrorStatus::NoSuchAlert,
-                        "no such frame" => ErrorStatus::NoSuchFrame,
-                        "no such window" => ErrorStatus::NoSuchWindow,
-                        "stale element reference" => ErrorStatus::StaleElementReference,
-                        e => bail!("StatusCode::BadRequest: {}", e),
-                    }
-                } else if status == StatusCode::NOT_FOUND {
-                    match error {
-                        "unknown command" => ErrorStatus::UnknownCommand,
-                        "no such cookie" => ErrorStatus::NoSuchCookie,
-                        "invalid session id" => ErrorStatus::InvalidSessionId,
-                        "no such element" => ErrorStatus::NoSuchElement,
-                        "no such frame" => ErrorStatus::NoSuchFrame,
-                        "no such window" => ErrorStatus::NoSuchWindow,
-                        e => bail!("StatusCode::NotFound: {}", e),
-                    }
-                } else if status == StatusCode::INTERNAL_SERVER_ERROR {
-                    match error {
-                        "javascript error" => ErrorStatus::JavascriptError,
-                        "move target out of bounds" => ErrorStatus::MoveTargetOutOfBounds,
-                        "session not created" => ErrorStatus::SessionNotCreated,
-                        "unable to set cookie" => ErrorStatus::UnableToSetCookie,
-                        "unable to capture screen" => ErrorStatus::UnableToCaptureScreen,
-                        "unexpected alert open" => ErrorStatus::UnexpectedAlertOpen,
-                        "unknown error" => ErrorStatus::UnknownError,
-                        "unsupported operation" => ErrorStatus::UnsupportedOperation,
-                        e => bail!("StatusCode::InternalServerError: {}", e),
-                    }
-                } else if status == StatusCode::REQUEST_TIMEOUT {
-                    match error {
-                        "timeout" => ErrorStatus::Timeout,
-                        "script timeout" => ErrorStatus::ScriptTimeout,
-                        e => bail!("StatusCode::RequestTimeout: {}", e),
-                    }
-                } else if status == StatusCode::METHOD_NOT_ALLOWED {
-                    match error {
-                        "unknown method" => ErrorStatus::UnknownMethod,
-                        e => bail!("StatusCode::MethodNotAllowed: {}", e),
-                    }
-                } else {
+                // the error code identifies the error, servers don't
+                // agree on the HTTP status of every error (e.g. stale
+                // element reference is 400 in older drivers, 404 in
+                // the spec), so it is only checked to be an error
+                if !(status.is_client_error() || status.is_server_error()) {
                     bail!("invalid status code: {:?}", status)
                 }
+                match error {
+                    "element click intercepted" => ErrorStatus::ElementClickIntercepted,
+                    "element not selectable" => ErrorStatus::ElementNotSelectable,
+                    "element not interactable" => ErrorStatus::ElementNotInteractable,
+                    "insecure certificate" => ErrorStatus::InsecureCertificate,
+                    "invalid argument" => ErrorStatus::InvalidArgument,
+                    "invalid cookie domain" => ErrorStatus::InvalidCookieDomain,
+                    "invalid coordinates" => ErrorStatus::InvalidCoordinates,
+                    "invalid element state" => ErrorStatus::InvalidElementState,
+                    "invalid selector" => ErrorStatus::InvalidSelector,
+                    "invalid session id" => ErrorStatus::InvalidSessionId,
+                    "javascript error" => ErrorStatus::JavascriptError,
+                    "move target out of bounds" => ErrorStatus::MoveTargetOutOfBounds,
+                    "no such alert" => ErrorStatus::NoSuchAlert,
+                    "no such cookie" => ErrorStatus::NoSuchCookie,
+                    "no such element" => ErrorStatus::NoSuchElement,
+                    "no such frame" => ErrorStatus::NoSuchFrame,
+                    "no such window" => ErrorStatus::NoSuchWindow,
+                    "script timeout" => ErrorStatus::ScriptTimeout,
+                    "session not created" => ErrorStatus::SessionNotCreated,
+                    "stale element reference" => ErrorStatus::StaleElementReference,
+                    "timeout" => ErrorStatus::Timeout,
+                    "unable to capture screen" => ErrorStatus::UnableToCaptureScreen,
+                    "unable to set cookie" => ErrorStatus::UnableToSetCookie,
+                    "unexpected alert open" => ErrorStatus::UnexpectedAlertOpen,
+                    "unknown command" => ErrorStatus::UnknownCommand,
+                    "unknown error" => ErrorStatus::UnknownError,
+                    "unknown method" => ErrorStatus::UnknownMethod,
+                    "unsupported operation" => ErrorStatus::UnsupportedOperation,
+                    e => bail!("{:?}: {}", status, e),
+                }
             }
         };
-        let message = match response["message"].as_str() {
+        let message = match response.get("message").and_then(|m| m.as_str()) {
             None => bail!(ErrorKind::NotW3C(Value::Object(response))),
             Some(s) => String::from(s),
         };
@@ -179,6 +229,9 @@ impl Client {
         if let WebDriverCommand::NewSession(..) = *cmd {
             return Ok(self.webdriver_url.join("/session")?);
         }
+        if let WebDriverCommand::Status = *cmd {
+            return Ok(self.webdriver_url.join("/status")?);
+        }
         let base = {
             let sid = match self.session_id {
                 Some(ref s) => s,
@@ -193,16 +246,38 @@ impl Client {
                 base.join("url")
             }
             WebDriverCommand::GoBack => base.join("back"),
+            WebDriverCommand::GoForward => base.join("forward"),
+            WebDriverCommand::GetTitle => base.join("title"),
             WebDriverCommand::Refresh => base.join("refresh"),
             WebDriverCommand::GetPageSource => base.join("source"),
             WebDriverCommand::FindElement(..) => base.join("element"),
             WebDriverCommand::FindElements(..) => base.join("elements"),
-            WebDriverCommand::GetCookies => base.join("cookie"),
+            WebDriverCommand::GetActiveElement => base.join("element/active"),
+            WebDriverCommand::PerformActions(..) | WebDriverCommand::ReleaseActions => {
+                base.join("actions")
+            }
+            WebDriverCommand::GetCookies | WebDriverCommand::DeleteCookies => {
+                base.join("cookie")
+            }
             WebDriverCommand::ExecuteScript(..) if self.legacy => base.join("execute"),
             WebDriverCommand::ExecuteScript(..) => base.join("execute/sync"),
+            WebDriverCommand::ExecuteAsyncScript(..) if self.legacy => {
+                base.join("execute_async")
+            }
+            WebDriverCommand::ExecuteAsyncScript(..) => base.join("execute/async"),
             WebDriverCommand::SwitchToFrame(..) => base.join("frame"),
             WebDriverCommand::SwitchToParentFrame => base.join("frame/parent"),
-            WebDriverCommand::SwitchToWindow(..) => base.join("window"),
+            WebDriverCommand::GetWindowHandle if self.legacy => {
+                base.join("window_handle")
+            }
+            WebDriverCommand::GetWindowHandles if self.legacy => {
+                base.join("window_handles")
+            }
+            WebDriverCommand::SwitchToWindow(..)
+            | WebDriverCommand::GetWindowHandle
+            | WebDriverCommand::CloseWindow => base.join("window"),
+            WebDriverCommand::GetWindowHandles => base.join("window/handles"),
+            WebDriverCommand::Print(..) => base.join("print"),
             WebDriverCommand::GetElementProperty(ref we, ref prop) => {
                 base.join(&format!("element/{}/property/{}", we.0, prop))
             }
@@ -224,17 +299,38 @@ impl Client {
             WebDriverCommand::ElementSendKeys(ref we, _) => {
                 base.join(&format!("element/{}/value", we.0))
             }
+            WebDriverCommand::Extension(ref ext) => match ext {
+                ExtensionCommand::GetShadowRoot(ref we) => {
+                    base.join(&format!("element/{}/shadow", we.0))
+                }
+                ExtensionCommand::FindShadowElement(ref sr, _) => {
+                    base.join(&format!("shadow/{}/element", sr))
+                }
+                ExtensionCommand::FindShadowElements(ref sr, _) => {
+                    base.join(&format!("shadow/{}/elements", sr))
+                }
+                ExtensionCommand::GetComputedRole(ref we) => {
+                    base.join(&format!("element/{}/computedrole", we.0))
+                }
+                ExtensionCommand::GetComputedLabel(ref we) => {
+                    base.join(&format!("element/{}/computedlabel", we.0))
+                }
+                ExtensionCommand::UploadFile(..) => base.join("se/file"),
+            },
             x => unimplemented!("{:?}", x),
         };
         Ok(endpoint?)
     }
 
-    fn encode_cmd(&self, cmd: &Cmd) -> Result<hyper::Request<hyper::Body>> {
+    fn encode_cmd(&self, cmd: &Cmd) -> Result<Request> {
         use webdriver::command;
         let (body, method) = match cmd {
             WebDriverCommand::NewSession(command::NewSessionParameters::Spec(
                 ref conf,
-            )) => (Some(serde_json::to_string(conf)?), Method::POST),
+            )) => {
+                let body = serde_json::json!({ "capabilities": conf });
+                (Some(body.to_string()), Method::POST)
+            }
             WebDriverCommand::NewSession(command::NewSessionParameters::Legacy(
                 ref conf,
             )) => (Some(serde_json::to_string(conf)?), Method::POST),
@@ -247,7 +343,8 @@ impl Client {
             | WebDriverCommand::FindElementElements(_, ref loc) => {
                 (Some(serde_json::to_string(loc)?), Method::POST)
             }
-            WebDriverCommand::ExecuteScript(ref script) => {
+            WebDriverCommand::ExecuteScript(ref script)
+            | WebDriverCommand::ExecuteAsyncScript(ref script) => {
                 (Some(serde_json::to_string(script)?), Method::POST)
             }
             WebDriverCommand::ElementSendKeys(_, ref keys) => {
@@ -255,6 +352,7 @@ impl Client {
             }
             WebDriverCommand::ElementClick(..)
             | WebDriverCommand::GoBack
+            | WebDriverCommand::GoForward
             | WebDriverCommand::Refresh => (Some("{}".to_string()), Method::POST),
             WebDriverCommand::SwitchToParentFrame => {
                 (Some("{}".to_string()), Method::POST)
@@ -263,63 +361,83 @@ impl Client {
                 // unfortunatly the serializer for this command does
                 // not round trip properly so we need to encode the
                 // Json manually.
-                let id = match param.id {
-                    Some(FrameId::Element(ref e)) => Value::String(e.0.to_string()),
-                    Some(FrameId::Short(_)) | None => unimplemented!(),
-                };
                 let p = move |k, v| {
                     let mut m = serde_json::map::Map::with_capacity(1);
                     m.insert(k, v);
                     Value::Object(m)
                 };
-                let msg = p("id".to_string(), p(ELEMENT_KEY.to_string(), id));
+                let id = match param.id {
+                    Some(FrameId::Element(ref e)) => {
+                        let key = if self.legacy { "ELEMENT" } else { ELEMENT_KEY };
+                        p(key.to_string(), Value::String(e.0.to_string()))
+                    }
+                    Some(FrameId::Short(n)) => Value::from(n),
+                    None => Value::Null,
+                };
+                let msg = p("id".to_string(), id);
                 (Some(format!("{}", msg)), Method::POST)
             }
             WebDriverCommand::SwitchToWindow(ref handle) => {
                 (Some(serde_json::to_string(handle)?), Method::POST)
             }
+            WebDriverCommand::Print(ref params) => {
+                (Some(serde_json::to_string(params)?), Method::POST)
+            }
+            WebDriverCommand::PerformActions(ref actions) => {
+                (Some(serde_json::to_string(actions)?), Method::POST)
+            }
+            WebDriverCommand::ReleaseActions
+            | WebDriverCommand::CloseWindow
+            | WebDriverCommand::DeleteCookies => (None, Method::DELETE),
+            WebDriverCommand::Extension(ref ext) => match ext.parameters_json() {
+                Some(params) => (Some(params.to_string()), Method::POST),
+                None => (None, Method::GET),
+            },
             _ => (None, Method::GET),
         };
-        let url = self.endpoint_for(&cmd)?;
-        let req = hyper::Request::builder().method(method).uri(url.as_str());
-        let req = match self.user_agent {
-            None => req,
-            Some(ref s) => req.header(hyper::header::USER_AGENT, s.as_str()),
-        };
-        match body {
-            None => Ok(req.body(hyper::Body::from(String::new()))?),
-            Some(body) => Ok(req
-                .header(
-                    hyper::header::CONTENT_TYPE,
-                    "application/json; charset=utf-8",
-                )
-                .header(hyper::header::CONTENT_LENGTH, body.len() as u64)
-                .body(hyper::Body::from(body))?),
+        let mut headers = Vec::new();
+        if let Some(ref s) = self.user_agent {
+            headers.push(("user-agent".to_string(), s.clone()));
+        }
+        if let Some(ref body) = body {
+            headers.push((
+                "content-type".to_string(),
+                "application/json; charset=utf-8".to_string(),
+            ));
+            headers.push(("content-length".to_string(), body.len().to_string()));
         }
+        Ok(Request {
+            method,
+            url: self.endpoint_for(cmd)?,
+            headers,
+            body,
+        })
     }
 
-    /// Create a new webdriver session with the server specified by url
+    /// Create a new webdriver session with the server specified by
+    /// url, sending requests through `transport`, and writing them to
+    /// `recorder` if given
     pub(crate) async fn new(
         webdriver_url: &str,
         user_agent: Option<String>,
+        capabilities: webdriver::capabilities::Capabilities,
+        transport: Arc<dyn Transport>,
+        recorder: Option<Recorder>,
     ) -> Result<Self> {
         let webdriver_url = webdriver_url.parse::<url::Url>()?;
-        let http_client =
-            hyper::Client::builder().build(hyper_tls::HttpsConnector::new());
         let mut client = Client {
-            http_client,
+            transport,
+            recorder,
             webdriver_url,
             user_agent,
             legacy: false,
             session_id: None,
         };
         let cap = {
-            let mut c = webdriver::capabilities::Capabilities::new();
+            let mut c = capabilities;
             // we want the browser to wait for the page to load
-            c.insert(
-                "pageLoadStrategy".to_string(),
-                Value::String("normal".to_string()),
-            );
+            c.entry("pageLoadStrategy".to_string())
+                .or_insert_with(|| Value::String("normal".to_string()));
             c
         };
         let session_config = webdriver::capabilities::SpecNewSessionParameters {
@@ -392,45 +510,31 @@ impl Client {
     /// failed.
     pub(crate) async fn issue_cmd<'a>(&'a self, cmd: &'a Cmd) -> Result<Value> {
         let req = self.encode_cmd(cmd)?;
-        let res = self.http_client.request(req).await?;
-        match res.headers().get(hyper::header::CONTENT_TYPE) {
+        let res = self.send(|| command_name(cmd), req).await?;
+        match res.header("content-type") {
             None => bail!(ErrorKind::NotJson(None)),
             Some(ctype) => {
-                if !ctype.to_str()?.starts_with("application/json") {
-                    let c = Some(String::from(ctype.to_str()?));
-                    bail!(ErrorKind::NotJson(c));
+                if !ctype.starts_with("application/json") {
+                    bail!(ErrorKind::NotJson(Some(ctype.to_string())));
                 }
             }
         }
-        let status = res.status();
-        let res_body = {
-            let mut buf = BytesMut::new();
-            let mut body = res.into_body();
-            loop {
-                match body.next().await {
-                    Some(r) => { buf.extend_from_slice(&*(r?)); }
-                    None => {
-                        if buf.len() == 0 {
-                            bail!("empty body");
-                        } else {
-                            break buf.split().freeze();
-                        }
-                    }
-                }
-            }
-        };
-        let is_new_session = if let WebDriverCommand::NewSession(..) = cmd {
-            true
-        } else {
-            false
-        };
+        let status = res.status;
+        if res.body.is_empty() {
+            bail!("empty body");
+        }
+        let res_body = res.body;
+        let is_new_session = matches!(cmd, WebDriverCommand::NewSession(..));
         let (response, is_success, legacy_status) =
-            match serde_json::from_str(from_utf8(&*res_body)?)? {
+            match serde_json::from_str(from_utf8(&res_body)?)? {
                 Value::Object(mut v) => {
                     let mut is_success = status.is_success();
                     let mut legacy_status = 0;
                     if self.legacy {
-                        legacy_status = v["status"].as_u64().unwrap();
+                        legacy_status = match v.get("status").and_then(|s| s.as_u64()) {
+                            Some(s) => s,
+                            None => bail!(ErrorKind::NotW3C(Value::Object(v))),
+                        };
                         is_success = legacy_status == 0;
                     }
                     if self.legacy && is_new_session {
@@ -451,3 +555,16 @@ impl Client {
         }
     }
 }
+
+/// The name of `cmd`'s variant, e.g. `FindElement`, for the cassette.
+/// This formats the whole command, so is only done when recording.
+fn command_name(cmd: &Cmd) -> String {
+    let name = match cmd {
+        WebDriverCommand::Extension(e) => format!("{:?}", e),
+        cmd => format!("{:?}", cmd),
+    };
+    name.split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
@@ -0,0 +1,343 @@
+//! Typed builders for CSS selectors and XPath expressions that take
+//! care of quoting and escaping, so arbitrary strings (names, labels,
+//! ids scraped from a page) can be safely used in a `Locator`.
+//!
+//! ```text
+//! let l: Locator = Css::tag("input").attr_eq("name", "q").into();
+//! let l: Locator = XPath::tag("button").text_eq("Don't \"save\"").into();
+//! ```
+
+use crate::Locator;
+use std::fmt::Write;
+
+/// Escape `s` for use as a CSS identifier (e.g. an id or class
+/// name), following the algorithm of `CSS.escape`.
+pub fn css_ident(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let first_dash = s.starts_with('-');
+    for (i, c) in s.chars().enumerate() {
+        match c {
+            '\0' => res.push('\u{FFFD}'),
+            '\u{1}'..='\u{1F}' | '\u{7F}' => {
+                let _ = write!(res, "\\{:x} ", c as u32);
+            }
+            '0'..='9' if i == 0 || (i == 1 && first_dash) => {
+                let _ = write!(res, "\\{:x} ", c as u32);
+            }
+            '-' if i == 0 && s.len() == 1 => res.push_str("\\-"),
+            c if c as u32 >= 0x80 || c == '-' || c == '_' || c.is_ascii_alphanumeric() => {
+                res.push(c)
+            }
+            c => {
+                res.push('\\');
+                res.push(c)
+            }
+        }
+    }
+    res
+}
+
+/// Quote `s` as a CSS string literal, e.g. for an attribute value.
+pub fn css_string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '\0' => res.push('\u{FFFD}'),
+            '"' | '\\' => {
+                res.push('\\');
+                res.push(c)
+            }
+            '\u{1}'..='\u{1F}' | '\u{7F}' => {
+                let _ = write!(res, "\\{:x} ", c as u32);
+            }
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// Quote `s` as an XPath string literal. XPath 1.0 has no escape
+/// sequences, so strings containing both kinds of quote are built
+/// with `concat()`.
+pub fn xpath_string(s: &str) -> String {
+    if !s.contains('\'') {
+        format!("'{}'", s)
+    } else if !s.contains('"') {
+        format!("\"{}\"", s)
+    } else {
+        let parts = s
+            .split('\'')
+            .map(|p| format!("'{}'", p))
+            .collect::<Vec<_>>()
+            .join(", \"'\", ");
+        format!("concat({})", parts)
+    }
+}
+
+/// A CSS selector builder.
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+pub struct Css(String);
+
+impl Css {
+    /// Match any element
+    pub fn any() -> Self {
+        Css("*".into())
+    }
+
+    /// Match elements with the given tag name
+    pub fn tag(name: &str) -> Self {
+        Css(css_ident(name))
+    }
+
+    /// Match elements with the given id
+    pub fn id(id: &str) -> Self {
+        Css(format!("#{}", css_ident(id)))
+    }
+
+    /// Match elements with the given class
+    pub fn class(class: &str) -> Self {
+        Css(format!(".{}", css_ident(class)))
+    }
+
+    /// Use a hand written selector as the starting point
+    pub fn raw(selector: &str) -> Self {
+        Css(selector.into())
+    }
+
+    /// Additionally require the given id
+    pub fn with_id(mut self, id: &str) -> Self {
+        let _ = write!(self.0, "#{}", css_ident(id));
+        self
+    }
+
+    /// Additionally require the given class
+    pub fn with_class(mut self, class: &str) -> Self {
+        let _ = write!(self.0, ".{}", css_ident(class));
+        self
+    }
+
+    /// Require the attribute `name` to be present
+    pub fn attr(mut self, name: &str) -> Self {
+        let _ = write!(self.0, "[{}]", css_ident(name));
+        self
+    }
+
+    /// Require the attribute `name` to equal `value`
+    pub fn attr_eq(mut self, name: &str, value: &str) -> Self {
+        let _ = write!(self.0, "[{}={}]", css_ident(name), css_string(value));
+        self
+    }
+
+    /// Require the attribute `name` to equal `value` ignoring ASCII case
+    pub fn attr_eq_i(mut self, name: &str, value: &str) -> Self {
+        let _ = write!(self.0, "[{}={} i]", css_ident(name), css_string(value));
+        self
+    }
+
+    /// Require the attribute `name` to contain `value`
+    pub fn attr_contains(mut self, name: &str, value: &str) -> Self {
+        let _ = write!(self.0, "[{}*={}]", css_ident(name), css_string(value));
+        self
+    }
+
+    /// Match `other` when it is a direct child of this selector
+    pub fn child(mut self, other: Css) -> Self {
+        let _ = write!(self.0, " > {}", other.0);
+        self
+    }
+
+    /// Match `other` when it is a descendant of this selector
+    pub fn descendant(mut self, other: Css) -> Self {
+        let _ = write!(self.0, " {}", other.0);
+        self
+    }
+
+    /// Match either this selector or `other`. The result is a
+    /// selector list, which can't be refined further, so build each
+    /// alternative completely first.
+    pub fn or(self, other: Css) -> CssGroup {
+        CssGroup(vec![self, other])
+    }
+
+    /// The selector text
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<Css> for Locator {
+    fn from(c: Css) -> Self {
+        Locator::Css(c.0)
+    }
+}
+
+/// A list of alternative CSS selectors, matching elements that match
+/// any of them. See `Css::or`.
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+pub struct CssGroup(Vec<Css>);
+
+impl CssGroup {
+    /// Also match `other`
+    pub fn or(mut self, other: Css) -> Self {
+        self.0.push(other);
+        self
+    }
+
+    /// The selector list text
+    pub fn to_selector(&self) -> String {
+        self.0
+            .iter()
+            .map(Css::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl From<CssGroup> for Locator {
+    fn from(g: CssGroup) -> Self {
+        Locator::Css(g.to_selector())
+    }
+}
+
+/// An XPath expression builder. Each step is relative to the
+/// previous one, starting from anywhere in the document.
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+pub struct XPath(String);
+
+impl XPath {
+    /// Match any element
+    pub fn any() -> Self {
+        XPath("//*".into())
+    }
+
+    /// Match elements with the given tag name
+    pub fn tag(name: &str) -> Self {
+        XPath(format!("//{}", name))
+    }
+
+    /// Require the attribute `name` to equal `value`
+    pub fn attr_eq(mut self, name: &str, value: &str) -> Self {
+        let _ = write!(self.0, "[@{}={}]", name, xpath_string(value));
+        self
+    }
+
+    /// Require the attribute `name` to contain `value`
+    pub fn attr_contains(mut self, name: &str, value: &str) -> Self {
+        let _ = write!(self.0, "[contains(@{}, {})]", name, xpath_string(value));
+        self
+    }
+
+    /// Require the normalized text content to equal `text`
+    pub fn text_eq(mut self, text: &str) -> Self {
+        let _ = write!(self.0, "[normalize-space(.)={}]", xpath_string(text));
+        self
+    }
+
+    /// Require the text content to contain `text`
+    pub fn text_contains(mut self, text: &str) -> Self {
+        let _ = write!(self.0, "[contains(., {})]", xpath_string(text));
+        self
+    }
+
+    /// Match `other` when it is a direct child of this expression
+    pub fn child(mut self, other: XPath) -> Self {
+        self.0.push_str(other.0.strip_prefix('/').unwrap_or(&other.0));
+        self
+    }
+
+    /// Match `other` when it is a descendant of this expression
+    pub fn descendant(mut self, other: XPath) -> Self {
+        self.0.push_str(&other.0);
+        self
+    }
+
+    /// The expression text
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<XPath> for Locator {
+    fn from(x: XPath) -> Self {
+        Locator::XPath(x.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ident_escaping() {
+        assert_eq!(css_ident("main"), "main");
+        assert_eq!(css_ident("a b"), "a\\ b");
+        assert_eq!(css_ident("a.b:c"), "a\\.b\\:c");
+        assert_eq!(css_ident("1st"), "\\31 st");
+        assert_eq!(css_ident("-2x"), "-\\32 x");
+        assert_eq!(css_ident("a1"), "a1");
+        assert_eq!(css_ident("-"), "\\-");
+        assert_eq!(css_ident("--x"), "--x");
+        assert_eq!(css_ident("_é"), "_é");
+        assert_eq!(css_ident("a\0b"), "a\u{FFFD}b");
+        assert_eq!(css_ident("a\nb"), "a\\a b");
+        assert_eq!(css_ident("it's"), "it\\'s");
+    }
+
+    #[test]
+    fn string_quoting() {
+        assert_eq!(css_string("plain"), "\"plain\"");
+        assert_eq!(css_string("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(css_string("it's"), "\"it's\"");
+        assert_eq!(css_string("a\\b"), "\"a\\\\b\"");
+        assert_eq!(css_string("a\nb"), "\"a\\a b\"");
+    }
+
+    #[test]
+    fn xpath_quoting() {
+        assert_eq!(xpath_string("plain"), "'plain'");
+        assert_eq!(xpath_string("it's"), "\"it's\"");
+        assert_eq!(xpath_string("say \"hi\""), "'say \"hi\"'");
+        assert_eq!(
+            xpath_string("Don't \"save\""),
+            "concat('Don', \"'\", 't \"save\"')"
+        );
+        assert_eq!(xpath_string("'"), "\"'\"");
+        assert_eq!(xpath_string("'\""), "concat('', \"'\", '\"')");
+    }
+
+    #[test]
+    fn css_builder() {
+        let c = Css::tag("input").attr_eq("name", "q").with_class("big");
+        assert_eq!(c.as_str(), "input[name=\"q\"].big");
+        let c = Css::id("1").child(Css::tag("a").attr("href"));
+        assert_eq!(c.as_str(), "#\\31  > a[href]");
+    }
+
+    #[test]
+    fn css_groups() {
+        let g = Css::tag("a").or(Css::tag("b")).or(Css::class("c"));
+        assert_eq!(g.to_selector(), "a, b, .c");
+        // alternatives are complete selectors, combinators can only
+        // be applied before `or`
+        let g = Css::tag("form")
+            .child(Css::tag("input"))
+            .or(Css::tag("form").child(Css::tag("button")));
+        assert_eq!(
+            Locator::from(g),
+            Locator::Css("form > input, form > button".into())
+        );
+    }
+
+    #[test]
+    fn xpath_builder() {
+        let x = XPath::tag("button").text_eq("Don't \"save\"");
+        assert_eq!(
+            x.as_str(),
+            "//button[normalize-space(.)=concat('Don', \"'\", 't \"save\"')]"
+        );
+        let x = XPath::tag("ul").child(XPath::tag("li").attr_eq("class", "x"));
+        assert_eq!(x.as_str(), "//ul/li[@class='x']");
+    }
+}
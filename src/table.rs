@@ -0,0 +1,246 @@
+//! Extracting the contents of HTML tables.
+
+use crate::{de::Text, error::*, Driver};
+use serde::{
+    de::{value, value::MapDeserializer, DeserializeOwned},
+    Deserialize,
+};
+use webdriver::common::WebElement;
+
+// Expands the rows of each section of the table arguments[0] into a
+// rectangular grid of cell text, copying cells that span several
+// columns or rows into each slot they cover. Row spans don't cross
+// sections, as in the HTML table model. Rows directly under the table,
+// e.g. added through the DOM without a <tbody>, are a body section.
+const TABLE_JS: &str = r#"
+    var table = arguments[0];
+    function text(c) { return c.innerText.replace(/\s+/g, ' ').trim(); }
+    function grid(rows) {
+        var out = [], carry = [];
+        for (var i = 0; i < rows.length; i++) {
+            var line = [], col = 0;
+            var fill = function() {
+                while (carry[col] && carry[col].left > 0) {
+                    line[col] = carry[col].text;
+                    carry[col].left--;
+                    col++;
+                }
+            };
+            for (var j = 0; j < rows[i].cells.length; j++) {
+                var c = rows[i].cells[j];
+                fill();
+                var t = text(c), cs = Math.max(1, c.colSpan);
+                var rs = c.rowSpan === 0 ? rows.length - i : Math.max(1, c.rowSpan);
+                for (var k = 0; k < cs; k++, col++) {
+                    line[col] = t;
+                    carry[col] = {text: t, left: rs - 1};
+                }
+            }
+            for (; col < carry.length; col++) {
+                if (carry[col] && carry[col].left > 0) {
+                    line[col] = carry[col].text;
+                    carry[col].left--;
+                }
+            }
+            for (var k = 0; k < line.length; k++) {
+                if (line[k] === undefined) { line[k] = ''; }
+            }
+            out.push(line);
+        }
+        return out;
+    }
+    var head = [], body = [], foot = [], first = null;
+    for (var i = 0; i < table.rows.length;) {
+        var parent = table.rows[i].parentNode, section = [];
+        while (i < table.rows.length && table.rows[i].parentNode === parent) {
+            section.push(table.rows[i++]);
+        }
+        if (parent.tagName === 'THEAD') {
+            head = head.concat(grid(section));
+        } else if (parent.tagName === 'TFOOT') {
+            foot = foot.concat(grid(section));
+        } else {
+            first = first || section[0];
+            body = body.concat(grid(section));
+        }
+    }
+    if (head.length === 0 && first) {
+        var allTh = first.cells.length > 0 &&
+            Array.prototype.every.call(first.cells, function(c) {
+                return c.tagName === 'TH';
+            });
+        if (allTh) { head = [body.shift()]; }
+    }
+    var cap = table.caption ? text(table.caption) : null;
+    return {caption: cap, head: head, body: body, foot: foot};
+"#;
+
+/// The contents of an HTML table, with cells spanning several rows or
+/// columns copied into every position they cover.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+pub struct Table {
+    pub caption: Option<String>,
+    /// The header rows, from `<thead>`, or if there is no `<thead>`
+    /// the first row if it consists entirely of `<th>` cells.
+    #[serde(rename = "head")]
+    pub header_rows: Vec<Vec<String>>,
+    /// The body rows, from all the `<tbody>` sections and the rows
+    /// outside of any section, in order
+    #[serde(rename = "body")]
+    pub rows: Vec<Vec<String>>,
+    /// The footer rows, from `<tfoot>`
+    #[serde(rename = "foot")]
+    pub footer_rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// The column names, taken from the last header row.
+    pub fn headers(&self) -> &[String] {
+        self.header_rows.last().map(|r| r.as_slice()).unwrap_or(&[])
+    }
+
+    /// The name of each column when deserializing rows: its header,
+    /// or its index, e.g. "0", if it has none. A name that is already
+    /// taken, e.g. by a header spanning several columns, is suffixed
+    /// with a number, e.g. "Price", "Price 2".
+    fn column_names(&self) -> Vec<String> {
+        let headers = self.headers();
+        let width = self.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut names: Vec<String> = Vec::with_capacity(width);
+        for i in 0..width.max(headers.len()) {
+            let base = match headers.get(i) {
+                Some(h) if !h.is_empty() => h.clone(),
+                _ => i.to_string(),
+            };
+            let mut name = base.clone();
+            let mut n = 1;
+            while names.contains(&name) {
+                n += 1;
+                name = format!("{} {}", base, n);
+            }
+            names.push(name);
+        }
+        names
+    }
+
+    /// Deserialize each body row into a `T`, with the fields named by
+    /// the column names, see `column_names`. Cells are parsed into the
+    /// type of their field: numbers are parsed from the text, a `bool`
+    /// is `true`/`yes` or `false`/`no`, and an empty cell is `None`
+    /// for an `Option` field.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<Vec<T>> {
+        let names = self.column_names();
+        self.rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let cells = names.iter().zip(row);
+                let cells = cells.map(|(name, cell)| (name.as_str(), Text::cell(cell)));
+                T::deserialize(MapDeserializer::<_, value::Error>::new(cells))
+                    .map_err(|e| Error::from(format!("row {}: {}", i + 1, e)))
+            })
+            .collect()
+    }
+}
+
+impl Driver {
+    /// Extract the contents of the `<table>` element `eid` in a single
+    /// round trip. Cell text is the rendered text with whitespace
+    /// normalized.
+    pub async fn table(&self, eid: WebElement) -> Result<Table> {
+        self.execute_typed(TABLE_JS.to_string(), (eid,)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Table;
+    use serde::Deserialize;
+
+    fn table(head: &[&str], body: &[&[&str]]) -> Table {
+        let row = |r: &[&str]| r.iter().map(|c| c.to_string()).collect();
+        Table {
+            header_rows: vec![row(head)],
+            rows: body.iter().map(|r| row(r)).collect(),
+            ..Table::default()
+        }
+    }
+
+    #[test]
+    fn typed_rows() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Item {
+            #[serde(rename = "Name")]
+            name: String,
+            #[serde(rename = "Price")]
+            price: f64,
+            #[serde(rename = "Qty")]
+            qty: Option<u32>,
+            #[serde(rename = "In stock")]
+            in_stock: bool,
+        }
+        let t = table(
+            &["Name", "Price", "Qty", "In stock"],
+            &[&["Pen", "1.50", "3", "Yes"], &["Ink", " 12 ", "", "no"]],
+        );
+        let items = t.deserialize::<Item>().unwrap();
+        assert_eq!(
+            items,
+            [
+                Item {
+                    name: "Pen".into(),
+                    price: 1.5,
+                    qty: Some(3),
+                    in_stock: true,
+                },
+                Item {
+                    name: "Ink".into(),
+                    price: 12.,
+                    qty: None,
+                    in_stock: false,
+                },
+            ]
+        );
+
+        let t = table(
+            &["Name", "Price", "Qty", "In stock"],
+            &[&["Pen", "n/a", "", "yes"]],
+        );
+        let err = t.deserialize::<Item>().unwrap_err().to_string();
+        assert!(err.starts_with("row 1: invalid value \"n/a\""), "{}", err);
+    }
+
+    #[test]
+    fn duplicate_and_missing_headers() {
+        let t = table(&["Price", "Price", "", "Price 2"], &[&["1", "2", "x", "3"]]);
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Row {
+            #[serde(rename = "Price")]
+            a: u8,
+            #[serde(rename = "Price 2")]
+            b: u8,
+            #[serde(rename = "2")]
+            c: String,
+            #[serde(rename = "Price 2 2")]
+            d: u8,
+        }
+        let rows = t.deserialize::<Row>().unwrap();
+        assert_eq!(
+            rows,
+            [Row {
+                a: 1,
+                b: 2,
+                c: "x".into(),
+                d: 3
+            }]
+        );
+
+        // a row wider than the headers
+        let t = table(&["a"], &[&["1", "2"]]);
+        let rows = t
+            .deserialize::<std::collections::BTreeMap<String, u8>>()
+            .unwrap();
+        assert_eq!(rows[0]["a"], 1);
+        assert_eq!(rows[0]["1"], 2);
+    }
+}
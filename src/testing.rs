@@ -0,0 +1,462 @@
+//! An in-process mock webdriver server, for testing without a browser.
+//!
+//! `MockServer` is a `Transport` that answers the driver's requests
+//! from scripted replies instead of sending them anywhere. It encodes
+//! replies the way a particular server does, including the quirks of
+//! legacy (JSON wire protocol) implementations, so the same test can
+//! be run against each `Flavor`. Creating, closing, and querying the
+//! session is scripted by default, everything else must be scripted
+//! by the test, and requests without a reply fail with `unknown
+//! command`.
+//!
+//! ```text
+//! let mock = MockServer::new(Flavor::Geckodriver);
+//! mock.on(Method::POST, "element", Reply::Element("e1".into()))
+//!     .on(Method::GET, "element/e1/text", Reply::Value(json!("Hello")));
+//! let driver = mock.driver().await?;
+//! let elt = driver.find(Locator::Css("h1".into()), None).await?;
+//! assert_eq!(driver.text(elt).await?, "Hello");
+//! let find = &mock.requests_to(Method::POST, "element")[0];
+//! assert_eq!(find.body["using"], "css selector");
+//! ```
+//!
+//! This module is only available with the `testing` feature.
+
+use crate::{
+    error::*,
+    transport::{Request, Response, Transport},
+    Driver,
+};
+use futures::future::{self, BoxFuture};
+use http::{Method, StatusCode};
+use serde_json::{json, Value};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use webdriver::{capabilities::Capabilities, common::ELEMENT_KEY, error::ErrorStatus};
+
+/// The webdriver server a `MockServer` behaves like.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum Flavor {
+    /// Firefox's geckodriver, a W3C implementation
+    Geckodriver,
+    /// Chrome's chromedriver, a W3C implementation
+    Chromedriver,
+    /// chromedriver <= 2.29, which rejects W3C sessions and speaks the
+    /// legacy JSON wire protocol
+    LegacyChromedriver,
+    /// PhantomJS' ghostdriver, which also speaks the legacy protocol,
+    /// and attaches a screenshot to every error
+    PhantomJs,
+}
+
+impl Flavor {
+    /// All the flavors, for running a test against each of them
+    pub const ALL: [Flavor; 4] = [
+        Flavor::Geckodriver,
+        Flavor::Chromedriver,
+        Flavor::LegacyChromedriver,
+        Flavor::PhantomJs,
+    ];
+
+    /// Does this flavor speak the legacy JSON wire protocol
+    pub fn is_legacy(&self) -> bool {
+        match self {
+            Flavor::Geckodriver | Flavor::Chromedriver => false,
+            Flavor::LegacyChromedriver | Flavor::PhantomJs => true,
+        }
+    }
+
+    fn browser_name(&self) -> &'static str {
+        match self {
+            Flavor::Geckodriver => "firefox",
+            Flavor::Chromedriver | Flavor::LegacyChromedriver => "chrome",
+            Flavor::PhantomJs => "phantomjs",
+        }
+    }
+}
+
+/// A scripted reply to a request.
+#[derive(Debug)]
+pub enum Reply {
+    /// Success, with the given value
+    Value(Value),
+    /// Success, with a reference to the element with the given id
+    Element(String),
+    /// Success, with references to the elements with the given ids
+    Elements(Vec<String>),
+    /// Failure, with the given message
+    Error(ErrorStatus, String),
+    /// A response sent exactly as given, e.g. to test malformed
+    /// responses
+    Raw {
+        status: StatusCode,
+        content_type: Option<String>,
+        body: String,
+    },
+}
+
+/// A request received by a `MockServer`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Recorded {
+    pub method: Method,
+    /// The path relative to the session, e.g. `element/e1/text`, or
+    /// for requests outside the session the absolute path without the
+    /// leading `/`, e.g. `status`
+    pub path: String,
+    /// The JSON body, `Null` if there was none
+    pub body: Value,
+    pub headers: Vec<(String, String)>,
+}
+
+type Handler = Box<dyn FnMut(&Recorded) -> Reply + Send>;
+
+enum Responder {
+    Fixed(Response),
+    Fn(Handler),
+}
+
+struct Route {
+    method: Method,
+    path: Vec<String>,
+    once: bool,
+    responder: Responder,
+}
+
+impl Route {
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        let segments = path.split('/').collect::<Vec<_>>();
+        self.method == method
+            && self.path.len() == segments.len()
+            && self.path.iter().zip(segments).all(|(p, s)| p == "*" || p == s)
+    }
+}
+
+struct State {
+    flavor: Flavor,
+    routes: Vec<Route>,
+    requests: Vec<Recorded>,
+    slept: Duration,
+}
+
+const SESSION_ID: &str = "mock-session";
+
+/// The HTTP status and legacy status code a flavor uses for `status`
+fn error_status(flavor: Flavor, status: &ErrorStatus) -> (StatusCode, u64) {
+    use ErrorStatus::*;
+    let http = match (flavor, status) {
+        // geckodriver uses the webdriver crate's mapping, chromedriver
+        // follows the spec where they differ
+        (Flavor::Chromedriver, UnableToCaptureScreen) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        (_, UnknownCommand) => StatusCode::NOT_FOUND,
+        (_, s) => s.http_status(),
+    };
+    let legacy = match status {
+        SessionNotCreated => 33,
+        NoSuchElement => 7,
+        NoSuchFrame => 8,
+        UnknownCommand | UnknownPath => 9,
+        StaleElementReference => 10,
+        ElementNotInteractable => 11,
+        InvalidElementState => 12,
+        ElementNotSelectable => 15,
+        JavascriptError => 17,
+        InvalidSelector => 32,
+        Timeout => 21,
+        NoSuchWindow => 23,
+        InvalidCookieDomain => 24,
+        UnableToSetCookie => 25,
+        UnexpectedAlertOpen => 26,
+        NoSuchAlert => 27,
+        ScriptTimeout => 28,
+        InvalidCoordinates => 29,
+        MoveTargetOutOfBounds => 34,
+        _ => 13,
+    };
+    (http, legacy)
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response {
+    Response {
+        status,
+        headers: vec![(
+            "content-type".to_string(),
+            "application/json; charset=utf-8".to_string(),
+        )],
+        body: body.to_string().into_bytes(),
+    }
+}
+
+/// Encode `reply` the way `flavor` would
+fn encode(flavor: Flavor, reply: Reply) -> Response {
+    let element = |id: String| {
+        let key = if flavor.is_legacy() { "ELEMENT" } else { ELEMENT_KEY };
+        json!({ key: id })
+    };
+    let value = match reply {
+        Reply::Value(v) => v,
+        Reply::Element(id) => element(id),
+        Reply::Elements(ids) => Value::Array(ids.into_iter().map(element).collect()),
+        Reply::Error(status, message) => {
+            let (http, legacy) = error_status(flavor, &status);
+            let body = match flavor {
+                Flavor::Geckodriver | Flavor::Chromedriver => {
+                    let error = match status {
+                        ErrorStatus::UnknownCommand => "unknown command",
+                        ref s => s.error_code(),
+                    };
+                    json!({
+                        "value": {"error": error, "message": message, "stacktrace": ""}
+                    })
+                }
+                Flavor::LegacyChromedriver => json!({
+                    "sessionId": SESSION_ID,
+                    "status": legacy,
+                    "value": {"message": message},
+                }),
+                Flavor::PhantomJs => json!({
+                    "sessionId": SESSION_ID,
+                    "status": legacy,
+                    "value": {"message": message, "screen": "iVBORw0KGgo="},
+                }),
+            };
+            return json_response(http, body);
+        }
+        Reply::Raw {
+            status,
+            content_type,
+            body,
+        } => {
+            return Response {
+                status,
+                headers: content_type
+                    .map(|c| vec![("content-type".to_string(), c)])
+                    .unwrap_or_default(),
+                body: body.into_bytes(),
+            }
+        }
+    };
+    if flavor.is_legacy() {
+        json_response(
+            StatusCode::OK,
+            json!({"sessionId": SESSION_ID, "status": 0, "value": value}),
+        )
+    } else {
+        json_response(StatusCode::OK, json!({ "value": value }))
+    }
+}
+
+/// Create a session the way `flavor` would, given the new session
+/// request `req`
+fn new_session(flavor: Flavor, req: &Recorded) -> Response {
+    // echo the requested capabilities back, as if they were all met
+    let requested = req
+        .body
+        .pointer("/capabilities/alwaysMatch")
+        .or_else(|| req.body.get("requiredCapabilities"))
+        .cloned();
+    let mut caps = json!({"browserName": flavor.browser_name()});
+    if let Some(Value::Object(requested)) = requested {
+        caps.as_object_mut().unwrap().extend(requested)
+    }
+    let is_legacy_request = req.body.get("desiredCapabilities").is_some();
+    match (flavor, is_legacy_request) {
+        (Flavor::Geckodriver, _) | (Flavor::Chromedriver, _) => json_response(
+            StatusCode::OK,
+            json!({"value": {"sessionId": SESSION_ID, "capabilities": caps}}),
+        ),
+        (Flavor::LegacyChromedriver, false) => json_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            json!({
+                "sessionId": "",
+                "status": 13,
+                "value": {
+                    "message": "unknown error: cannot find dict 'desiredCapabilities'"
+                },
+            }),
+        ),
+        (Flavor::PhantomJs, false) => json_response(
+            StatusCode::BAD_REQUEST,
+            json!({
+                "value": "Missing Command Parameter - {\"name\":\"desiredCapabilities\"}"
+            }),
+        ),
+        (_, true) => json_response(
+            StatusCode::OK,
+            json!({"sessionId": SESSION_ID, "status": 0, "value": caps}),
+        ),
+    }
+}
+
+/// An in-process mock webdriver server, see the module documentation.
+/// Clones share the same routes and request log.
+#[derive(Clone)]
+pub struct MockServer(Arc<Mutex<State>>);
+
+impl MockServer {
+    /// The url to create sessions on the mock server with
+    pub const URL: &'static str = "http://webdriver.mock/";
+
+    /// A mock server behaving like `flavor`, with the session
+    /// commands scripted.
+    pub fn new(flavor: Flavor) -> Self {
+        let mock = MockServer(Arc::new(Mutex::new(State {
+            flavor,
+            routes: Vec::new(),
+            requests: Vec::new(),
+            slept: Duration::from_secs(0),
+        })));
+        let window = if flavor.is_legacy() { "window_handle" } else { "window" };
+        mock.on(Method::GET, window, Reply::Value(json!("window-1")))
+            .on(Method::DELETE, "", Reply::Value(Value::Null))
+            .on(
+                Method::GET,
+                "status",
+                Reply::Value(json!({"ready": true, "message": "ready"})),
+            );
+        mock
+    }
+
+    pub fn flavor(&self) -> Flavor {
+        self.0.lock().unwrap().flavor
+    }
+
+    fn route(
+        &self,
+        method: Method,
+        path: &str,
+        once: bool,
+        responder: Responder,
+    ) -> &Self {
+        self.0.lock().unwrap().routes.push(Route {
+            method,
+            path: path.split('/').map(String::from).collect(),
+            once,
+            responder,
+        });
+        self
+    }
+
+    /// Reply to every `method` request to `path` with `reply`. `*`
+    /// matches any one segment of the path, e.g. `element/*/text`.
+    /// Routes added later take precedence over earlier ones.
+    pub fn on(&self, method: Method, path: &str, reply: Reply) -> &Self {
+        let res = encode(self.flavor(), reply);
+        self.route(method, path, false, Responder::Fixed(res))
+    }
+
+    /// Reply to the next `method` request to `path` with `reply`,
+    /// after which the route is removed. Queuing several replies to
+    /// the same path scripts a sequence, used in the order they were
+    /// queued.
+    pub fn once(&self, method: Method, path: &str, reply: Reply) -> &Self {
+        let res = encode(self.flavor(), reply);
+        self.route(method, path, true, Responder::Fixed(res))
+    }
+
+    /// Reply to every `method` request to `path` with the result of
+    /// calling `f` with the request. `f` must not call the mock
+    /// server.
+    pub fn on_fn<F>(&self, method: Method, path: &str, f: F) -> &Self
+    where
+        F: FnMut(&Recorded) -> Reply + Send + 'static,
+    {
+        self.route(method, path, false, Responder::Fn(Box::new(f)))
+    }
+
+    /// All the requests received so far, in order
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.0.lock().unwrap().requests.clone()
+    }
+
+    /// The requests received so far to exactly `path` with `method`
+    pub fn requests_to(&self, method: Method, path: &str) -> Vec<Recorded> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method == method && r.path == path)
+            .collect()
+    }
+
+    /// The total time the driver has slept, e.g. while waiting for an
+    /// element to appear. The mock server doesn't actually sleep.
+    pub fn slept(&self) -> Duration {
+        self.0.lock().unwrap().slept
+    }
+
+    /// Create a session on the mock server
+    pub async fn driver(&self) -> Result<Driver> {
+        let transport = Arc::new(self.clone());
+        Driver::new_with_transport(Self::URL, None, Capabilities::new(), transport).await
+    }
+
+    fn handle(&self, request: Request) -> Result<Response> {
+        let path = request.url.path().trim_start_matches('/');
+        let prefix = format!("session/{}", SESSION_ID);
+        let path = match path.strip_prefix(&prefix) {
+            Some(rest) => rest.trim_start_matches('/'),
+            None => path,
+        };
+        let body = match request.body {
+            None => Value::Null,
+            Some(ref b) => serde_json::from_str(b)?,
+        };
+        let req = Recorded {
+            method: request.method,
+            path: path.to_string(),
+            body,
+            headers: request.headers,
+        };
+        let mut st = self.0.lock().unwrap();
+        st.requests.push(req.clone());
+        let flavor = st.flavor;
+        if req.method == Method::POST && req.path == "session" {
+            return Ok(new_session(flavor, &req));
+        }
+        let i = st.routes.iter().rposition(|r| r.matches(&req.method, &req.path));
+        // the replies queued with `once` for a route are used in order
+        let i = i.map(|i| {
+            let route = &st.routes[i];
+            let queued =
+                |r: &Route| r.once && r.method == route.method && r.path == route.path;
+            match route.once {
+                true => st.routes.iter().position(queued).unwrap_or(i),
+                false => i,
+            }
+        });
+        match i {
+            None => {
+                let msg = format!("no reply scripted for {} {}", req.method, req.path);
+                Ok(encode(flavor, Reply::Error(ErrorStatus::UnknownCommand, msg)))
+            }
+            Some(i) => {
+                let route = if st.routes[i].once {
+                    st.routes.remove(i)
+                } else {
+                    let route = &mut st.routes[i];
+                    return Ok(match route.responder {
+                        Responder::Fixed(ref res) => res.clone(),
+                        Responder::Fn(ref mut f) => encode(flavor, f(&req)),
+                    });
+                };
+                Ok(match route.responder {
+                    Responder::Fixed(res) => res,
+                    Responder::Fn(mut f) => encode(flavor, f(&req)),
+                })
+            }
+        }
+    }
+}
+
+impl Transport for MockServer {
+    fn send(&self, request: Request) -> BoxFuture<'static, Result<Response>> {
+        Box::pin(future::ready(self.handle(request)))
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.0.lock().unwrap().slept += duration;
+        Box::pin(future::ready(()))
+    }
+}
@@ -0,0 +1,183 @@
+//! The HTTP transport used to talk to the webdriver server.
+//!
+//! Every request the driver makes goes through a `Transport`, which
+//! also provides the timer used while polling (e.g. `wait_for_find`),
+//! so the crate isn't tied to a particular async runtime or TLS
+//! library. Transports built on hyper and tokio are provided behind
+//! cargo features:
+//!
+//! - `native-tls` (the default): hyper with the platform's TLS library
+//! - `rustls`: hyper with rustls and the Mozilla root certificates
+//! - `plain-http`: hyper without any TLS support, e.g. for a server on
+//!   localhost
+//!
+//! To use another runtime or HTTP client, implement `Transport` and
+//! create the session with `Driver::new_with_transport`. Override
+//! `Transport::spawn` as well when not running on tokio: it runs the
+//! work done when a handle is dropped, e.g. closing the session when
+//! the last `Driver` is dropped, or returning a `PooledDriver` to its
+//! pool, and by default that work is skipped outside of tokio.
+
+use crate::error::*;
+use futures::future::BoxFuture;
+use http::{Method, StatusCode};
+use std::{sync::Arc, time::Duration};
+
+/// A request to the webdriver server.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Request {
+    pub method: Method,
+    pub url: url::Url,
+    pub headers: Vec<(String, String)>,
+    /// The JSON encoded body, if any
+    pub body: Option<String>,
+}
+
+/// The response of the webdriver server. The whole body is read
+/// before it is returned.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// The first value of the header `name` (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Sends requests to the webdriver server, and waits, on behalf of a
+/// `Driver`.
+pub trait Transport: Send + Sync {
+    /// Send `request` and read the response.
+    fn send(&self, request: Request) -> BoxFuture<'static, Result<Response>>;
+
+    /// Complete after `duration` has elapsed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// Run `task` in the background. The default spawns it on the
+    /// current tokio runtime, and drops it if there is none.
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        if let Ok(rt) = tokio::runtime::Handle::try_current() {
+            rt.spawn(task);
+        }
+    }
+}
+
+/// The transport selected by the enabled cargo features, used by the
+/// `Driver` constructors that don't take one.
+pub fn default_transport() -> Result<Arc<dyn Transport>> {
+    #[cfg(feature = "native-tls")]
+    return Ok(Arc::new(HyperTransport::native_tls()));
+    #[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+    return Ok(Arc::new(HyperTransport::rustls()));
+    #[cfg(all(feature = "hyper", not(any(feature = "native-tls", feature = "rustls"))))]
+    return Ok(Arc::new(HyperTransport::http()));
+    #[cfg(not(feature = "hyper"))]
+    bail!(
+        "no default transport, enable one of the native-tls, rustls, or plain-http \
+         features, or use Driver::new_with_transport"
+    )
+}
+
+#[cfg(feature = "hyper")]
+pub use self::hyper_transport::HyperTransport;
+
+#[cfg(feature = "hyper")]
+mod hyper_transport {
+    use super::{Request, Response, Transport};
+    use crate::error::*;
+    use bytes::BytesMut;
+    use futures::{future::BoxFuture, prelude::*};
+    use hyper::client::{connect::Connect, HttpConnector};
+    use std::time::Duration;
+
+    /// A transport using hyper, on the tokio runtime.
+    #[derive(Clone)]
+    pub struct HyperTransport<C> {
+        client: hyper::Client<C, hyper::Body>,
+    }
+
+    impl<C> HyperTransport<C> {
+        /// A transport using the given hyper client, e.g. one with a
+        /// custom TLS configuration.
+        pub fn new(client: hyper::Client<C, hyper::Body>) -> Self {
+            HyperTransport { client }
+        }
+    }
+
+    impl HyperTransport<HttpConnector> {
+        /// A transport that only supports plain HTTP
+        pub fn http() -> Self {
+            Self::new(hyper::Client::builder().build(HttpConnector::new()))
+        }
+    }
+
+    #[cfg(feature = "native-tls")]
+    impl HyperTransport<hyper_tls::HttpsConnector<HttpConnector>> {
+        /// A transport supporting HTTP and HTTPS using the platform's
+        /// TLS library
+        pub fn native_tls() -> Self {
+            Self::new(hyper::Client::builder().build(hyper_tls::HttpsConnector::new()))
+        }
+    }
+
+    #[cfg(feature = "rustls")]
+    impl HyperTransport<hyper_rustls::HttpsConnector<HttpConnector>> {
+        /// A transport supporting HTTP and HTTPS using rustls, trusting
+        /// the Mozilla root certificates
+        pub fn rustls() -> Self {
+            let connector = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_webpki_roots()
+                .https_or_http()
+                .enable_http1()
+                .build();
+            Self::new(hyper::Client::builder().build(connector))
+        }
+    }
+
+    impl<C> Transport for HyperTransport<C>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        fn send(&self, request: Request) -> BoxFuture<'static, Result<Response>> {
+            let client = self.client.clone();
+            Box::pin(async move {
+                let mut req = hyper::Request::builder()
+                    .method(request.method)
+                    .uri(request.url.as_str());
+                for (k, v) in &request.headers {
+                    req = req.header(k.as_str(), v.as_str());
+                }
+                let req = req.body(hyper::Body::from(request.body.unwrap_or_default()))?;
+                let res = client.request(req).await?;
+                let status = res.status();
+                let headers = res
+                    .headers()
+                    .iter()
+                    .map(|(k, v)| Ok((k.to_string(), v.to_str()?.to_string())))
+                    .collect::<Result<Vec<_>>>()?;
+                let mut body = res.into_body();
+                let mut buf = BytesMut::new();
+                while let Some(chunk) = body.next().await {
+                    buf.extend_from_slice(&chunk?);
+                }
+                Ok(Response {
+                    status,
+                    headers,
+                    body: buf.to_vec(),
+                })
+            })
+        }
+
+        fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+            Box::pin(tokio::time::sleep(duration))
+        }
+    }
+}
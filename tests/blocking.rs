@@ -0,0 +1,37 @@
+#![cfg(feature = "blocking")]
+
+use http::Method;
+use rusty_driver::{
+    blocking,
+    testing::{Flavor, MockServer, Reply},
+};
+use serde_json::json;
+use std::sync::Arc;
+use webdriver::capabilities::Capabilities;
+
+fn connect(mock: &MockServer) -> blocking::Driver {
+    let transport = Arc::new(mock.clone());
+    let caps = Capabilities::new();
+    blocking::Driver::new_with_transport(MockServer::URL, None, caps, transport).unwrap()
+}
+
+#[test]
+fn blocking_driver() {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    let driver = connect(&mock);
+    mock.on(Method::GET, "title", Reply::Value(json!("Home")));
+    assert_eq!(driver.title().unwrap(), "Home");
+    driver.quit().unwrap();
+    assert_eq!(mock.requests_to(Method::DELETE, "").len(), 1);
+}
+
+#[test]
+fn drop_outside_runtime_closes_session() {
+    let mock = MockServer::new(Flavor::Chromedriver);
+    let driver = connect(&mock);
+    let clone = driver.clone();
+    drop(driver);
+    assert!(mock.requests_to(Method::DELETE, "").is_empty());
+    drop(clone);
+    assert_eq!(mock.requests_to(Method::DELETE, "").len(), 1);
+}
@@ -0,0 +1,343 @@
+use http::Method;
+use rusty_driver::{
+    cassette::{Entry, Matching, Recorder, Replay},
+    error::*,
+    fetch::FetchOptions,
+    testing::{Flavor, MockServer, Reply},
+    Driver, Locator,
+};
+use serde_json::{json, Value};
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+use webdriver::{
+    capabilities::Capabilities,
+    common::{WebElement, ELEMENT_KEY},
+    error::ErrorStatus,
+};
+
+/// An in-memory cassette
+#[derive(Clone, Default)]
+struct Tape(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Tape {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Tape {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        self.text()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+}
+
+async fn record(mock: &MockServer, recorder: Recorder) -> Driver {
+    let transport = Arc::new(mock.clone());
+    let caps = Capabilities::new();
+    Driver::new_with_recorder(MockServer::URL, None, caps, transport, recorder)
+        .await
+        .unwrap()
+}
+
+async fn replay(replay: &Arc<Replay>) -> Result<Driver> {
+    let caps = Capabilities::new();
+    Driver::new_with_transport("http://localhost:4444/", None, caps, replay.clone()).await
+}
+
+/// Find an element, with one miss, and read its text
+async fn scenario(driver: &Driver) -> Result<String> {
+    let elt = driver.wait_for_find(Locator::Css("h1".into()), None).await?;
+    assert_eq!(elt, WebElement("e1".into()));
+    driver.text(elt).await
+}
+
+fn script(mock: &MockServer) {
+    let missing = Reply::Error(ErrorStatus::NoSuchElement, "not yet".into());
+    mock.once(Method::POST, "element", missing)
+        .once(Method::POST, "element", Reply::Element("e1".into()))
+        .on(Method::GET, "element/e1/text", Reply::Value(json!("Hello")));
+}
+
+#[tokio::test]
+async fn record_and_replay() {
+    for flavor in Flavor::ALL {
+        let mock = MockServer::new(flavor);
+        script(&mock);
+        let tape = Tape::default();
+        let driver = record(&mock, Recorder::new(tape.clone())).await;
+        assert_eq!(scenario(&driver).await.unwrap(), "Hello");
+        driver.quit().await.unwrap();
+
+        let entries = tape.entries();
+        let commands = entries.iter().map(|e| e.command.as_str()).collect::<Vec<_>>();
+        let mut expected = vec!["NewSession"];
+        if flavor.is_legacy() {
+            // the spec request was rejected
+            expected.push("NewSession");
+        }
+        expected.extend([
+            "GetWindowHandle",
+            "FindElement",
+            "FindElement",
+            "GetElementText",
+            "DeleteSession",
+        ]);
+        assert_eq!(commands, expected, "{:?}", flavor);
+        let find = &entries[entries.len() - 4];
+        assert_eq!(find.method, "POST");
+        assert_eq!(find.path, "/session/mock-session/element");
+        assert_eq!(find.request.as_ref().unwrap()["value"], "h1");
+        assert!(find.status.is_some());
+        assert_eq!(find.response["value"]["message"], "not yet");
+
+        for matching in [Matching::Strict, Matching::Request] {
+            let cassette = Arc::new(Replay::new(entries.clone(), matching));
+            let driver = replay(&cassette).await.unwrap();
+            assert_eq!(scenario(&driver).await.unwrap(), "Hello");
+            driver.quit().await.unwrap();
+            assert!(cassette.remaining().is_empty(), "{:?}", flavor);
+        }
+    }
+}
+
+#[tokio::test]
+async fn replay_from_file() {
+    let mock = MockServer::new(Flavor::Chromedriver);
+    script(&mock);
+    let name = format!("cassette-{}.jsonl", std::process::id());
+    let path = std::env::temp_dir().join(name);
+    let driver = record(&mock, Recorder::create(&path).unwrap()).await;
+    scenario(&driver).await.unwrap();
+    driver.quit().await.unwrap();
+
+    let cassette = Arc::new(Replay::open(&path, Matching::Strict).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    let driver = replay(&cassette).await.unwrap();
+    assert_eq!(scenario(&driver).await.unwrap(), "Hello");
+}
+
+#[tokio::test]
+async fn matching() {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    mock.on(Method::GET, "title", Reply::Value(json!("Home")))
+        .on(Method::GET, "url", Reply::Value(json!("https://example.com/")));
+    let tape = Tape::default();
+    let driver = record(&mock, Recorder::new(tape.clone())).await;
+    driver.title().await.unwrap();
+    driver.current_url().await.unwrap();
+
+    // out of order
+    let strict = Arc::new(Replay::new(tape.entries(), Matching::Strict));
+    let driver = replay(&strict).await.unwrap();
+    match driver.current_url().await {
+        Err(Error(ErrorKind::CassetteMismatch(msg), _)) => {
+            assert!(msg.contains("expected GET /session/mock-session/title"), "{}", msg)
+        }
+        r => panic!("unexpected {:?}", r),
+    }
+
+    let matched = Arc::new(Replay::new(tape.entries(), Matching::Request));
+    let driver = replay(&matched).await.unwrap();
+    let url = driver.current_url().await.unwrap();
+    assert_eq!(url.as_str(), "https://example.com/");
+    assert_eq!(driver.title().await.unwrap(), "Home");
+    // each entry is only replayed once
+    match driver.title().await {
+        Err(Error(ErrorKind::CassetteMismatch(_), _)) => (),
+        r => panic!("unexpected {:?}", r),
+    }
+    assert!(matched.remaining().is_empty());
+}
+
+const FILL: &str = "arguments[0].value = arguments[1]";
+
+#[tokio::test]
+async fn scrubbing() {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    let current = "https://example.com/cb?access_token=url-secret&page=2";
+    let cookies = json!([{
+        "name": "sid",
+        "value": "cookie-secret",
+        "path": "/",
+        "httpOnly": true,
+    }]);
+    let fetched = json!({
+        "status": 200,
+        "headers": [["x-session-token", "response-secret"]],
+        "body": "",
+    });
+    mock.on(Method::GET, "url", Reply::Value(json!(current)))
+        .on(Method::POST, "url", Reply::Value(Value::Null))
+        .on(Method::POST, "execute/sync", Reply::Value(cookies))
+        .on(Method::POST, "execute/async", Reply::Value(fetched));
+    let tape = Tape::default();
+    let driver = record(&mock, Recorder::new(tape.clone()).redact("hunter2")).await;
+
+    driver.goto("/login?token=url-secret").await.unwrap();
+    let mut options = FetchOptions::default();
+    options.headers.push(("Authorization".into(), "Bearer header-secret".into()));
+    options.headers.push(("Cookie".into(), "sid=cookie-secret".into()));
+    driver.fetch("/api", options).await.unwrap();
+    let args = vec![json!({ ELEMENT_KEY: "e1" }), json!("hunter2")];
+    driver.execute(FILL.into(), args).await.unwrap();
+
+    let text = tape.text();
+    for secret in [
+        "url-secret",
+        "header-secret",
+        "cookie-secret",
+        "response-secret",
+        "hunter2",
+    ] {
+        assert!(!text.contains(secret), "{} in {}", secret, text);
+    }
+    let entries = tape.entries();
+    let goto = entries.iter().find(|e| e.command == "Get").unwrap();
+    let url = &goto.request.as_ref().unwrap()["url"];
+    assert_eq!(url, "https://example.com/login?token=%5Bscrubbed%5D");
+    let current = entries.iter().find(|e| e.command == "GetCurrentUrl").unwrap();
+    assert_eq!(
+        current.response["value"],
+        "https://example.com/cb?access_token=%5Bscrubbed%5D&page=2"
+    );
+    let script = entries.iter().find(|e| e.command == "ExecuteScript").unwrap();
+    assert_eq!(script.response["value"][0]["name"], "sid");
+    assert_eq!(script.response["value"][0]["value"], "[scrubbed]");
+}
+
+#[tokio::test]
+async fn scrubbed_requests_replay() {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    mock.on(Method::GET, "url", Reply::Value(json!("https://example.com/")))
+        .on(Method::POST, "url", Reply::Value(Value::Null))
+        .on(Method::POST, "execute/sync", Reply::Value(Value::Null));
+    let tape = Tape::default();
+    let driver = record(&mock, Recorder::new(tape.clone()).redact("hunter2")).await;
+    driver.goto("/?token=abc").await.unwrap();
+    driver.execute(FILL.into(), vec![json!("hunter2")]).await.unwrap();
+
+    // the replayed run uses other credentials
+    let cassette = Replay::new(tape.entries(), Matching::Strict).redact("swordfish");
+    let cassette = Arc::new(cassette);
+    let driver = replay(&cassette).await.unwrap();
+    driver.goto("/?token=xyz").await.unwrap();
+    driver.execute(FILL.into(), vec![json!("swordfish")]).await.unwrap();
+    assert!(cassette.remaining().is_empty());
+}
+
+#[tokio::test]
+async fn replayed_failures() {
+    let entry = |status, error: Option<&str>| Entry {
+        command: "GetTitle".into(),
+        method: "GET".into(),
+        path: "/session/s/title".into(),
+        request: None,
+        status,
+        content_type: Some("text/plain".into()),
+        response: json!("Bad Gateway"),
+        error: error.map(String::from),
+    };
+    let session = Entry {
+        command: "NewSession".into(),
+        method: "POST".into(),
+        path: "/session".into(),
+        request: Some(json!({
+            "capabilities": {
+                "alwaysMatch": {"pageLoadStrategy": "normal"},
+                "firstMatch": [],
+            }
+        })),
+        status: Some(200),
+        content_type: Some("application/json".into()),
+        response: json!({"value": {"sessionId": "s", "capabilities": {}}}),
+        error: None,
+    };
+    let window = Entry {
+        command: "GetWindowHandle".into(),
+        method: "GET".into(),
+        path: "/session/s/window".into(),
+        request: None,
+        status: Some(200),
+        content_type: Some("application/json".into()),
+        response: json!({"value": "w"}),
+        error: None,
+    };
+    let entries = vec![
+        session,
+        window,
+        entry(None, Some("connection reset")),
+        entry(Some(502), None),
+    ];
+    let cassette = Arc::new(Replay::new(entries, Matching::Strict));
+    let driver = replay(&cassette).await.unwrap();
+    let err = driver.title().await.unwrap_err();
+    assert_eq!(err.to_string(), "connection reset");
+    match driver.title().await {
+        Err(Error(ErrorKind::NotJson(Some(c)), _)) => assert_eq!(c, "text/plain"),
+        r => panic!("unexpected {:?}", r),
+    }
+}
+
+#[tokio::test]
+async fn empty_secrets_are_ignored() {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    mock.on(Method::GET, "title", Reply::Value(json!("Home")));
+    let tape = Tape::default();
+    let driver = record(&mock, Recorder::new(tape.clone()).redact("")).await;
+    driver.title().await.unwrap();
+    let title = tape.entries().into_iter().find(|e| e.command == "GetTitle");
+    assert_eq!(title.unwrap().response["value"], "Home");
+
+    let cassette = Arc::new(Replay::new(tape.entries(), Matching::Strict).redact(""));
+    let driver = replay(&cassette).await.unwrap();
+    assert_eq!(driver.title().await.unwrap(), "Home");
+}
+
+/// A cassette with room for `1` bytes
+struct Full(Tape, usize);
+
+impl Write for Full {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.0.text().len() + buf.len() > self.1 {
+            return Err(std::io::Error::other("disk full"));
+        }
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn failed_writes_stop_the_recording() {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    let long = "x".repeat(4096);
+    mock.on(Method::GET, "title", Reply::Value(json!(long)))
+        .on(Method::GET, "url", Reply::Value(json!("https://example.com/")));
+    let tape = Tape::default();
+    let recorder = Recorder::new(Full(tape.clone(), 2048));
+    let driver = record(&mock, recorder.clone()).await;
+    recorder.check().unwrap();
+    let recorded = tape.entries().len();
+
+    // the command succeeds, but the recording stops
+    assert_eq!(driver.title().await.unwrap(), long);
+    driver.current_url().await.unwrap();
+    assert_eq!(tape.entries().len(), recorded);
+    let err = recorder.check().unwrap_err();
+    assert_eq!(err.to_string(), "writing the cassette failed: disk full");
+}
@@ -0,0 +1,170 @@
+use base64::Engine;
+use http::Method;
+use rusty_driver::{
+    crawler::{CrawlConfig, Crawler, Handler, Page, Visit},
+    error::*,
+    testing::{Flavor, MockServer, Reply},
+    Driver,
+};
+use serde_json::{json, Value};
+use std::time::Duration;
+use url::Url;
+use webdriver::error::ErrorStatus;
+
+/// Links `/` to `/broken` and `/ok`, and fails on `/broken`
+#[derive(Default)]
+struct Site {
+    handled: Vec<String>,
+}
+
+impl Handler for Site {
+    type Item = String;
+
+    async fn handle(&mut self, _driver: &Driver, page: &Page) -> Result<Visit<String>> {
+        let path = page.url.path().to_string();
+        self.handled.push(path.clone());
+        let links = match path.as_str() {
+            "/" => vec![
+                page.url.join("/broken")?,
+                page.url.join("/ok#top")?,
+                Url::parse("https://elsewhere.com/")?,
+            ],
+            "/broken" => return Err(format!("no items on {}", path).into()),
+            _ => vec![],
+        };
+        Ok(Visit {
+            items: vec![path],
+            links,
+        })
+    }
+}
+
+fn config() -> CrawlConfig {
+    CrawlConfig {
+        politeness: Duration::from_secs(0),
+        respect_robots: false,
+        max_retries: 1,
+        ..CrawlConfig::default()
+    }
+}
+
+async fn setup() -> (MockServer, Driver) {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    mock.on(Method::POST, "url", Reply::Value(Value::Null))
+        .on(Method::GET, "url", Reply::Value(json!("https://example.com/")));
+    let driver = mock.driver().await.unwrap();
+    (mock, driver)
+}
+
+#[tokio::test]
+async fn failed_pages_are_retried_then_skipped() {
+    let (mock, driver) = setup().await;
+    let mut crawler = Crawler::new(config(), &["https://example.com/"]).unwrap();
+    let mut site = Site::default();
+    let items = crawler.run(&driver, &mut site).await.unwrap();
+    assert_eq!(items, ["/", "/ok"]);
+    assert_eq!(site.handled, ["/", "/broken", "/ok", "/broken"]);
+    assert_eq!(crawler.visited(), 2);
+    assert_eq!(crawler.pending(), 0);
+    assert_eq!(
+        crawler.failed(),
+        [(
+            "https://example.com/broken".to_string(),
+            "no items on /broken".to_string()
+        )]
+    );
+    assert_eq!(mock.requests_to(Method::POST, "url").len(), 4);
+}
+
+#[tokio::test]
+async fn fatal_errors_stop_the_crawl() {
+    let (mock, driver) = setup().await;
+    let name = format!("crawl-{}.json", std::process::id());
+    let checkpoint = std::env::temp_dir().join(name);
+    let config = CrawlConfig {
+        checkpoint: Some(checkpoint.clone()),
+        ..config()
+    };
+    let mut crawler = Crawler::new(config.clone(), &["https://example.com/"]).unwrap();
+    let mut site = Site::default();
+    let mut items = crawler.step(&driver, &mut site).await.unwrap().unwrap().1;
+    assert_eq!(items, ["/"]);
+
+    let gone = Reply::Error(ErrorStatus::InvalidSessionId, "session deleted".into());
+    mock.once(Method::POST, "url", gone);
+    let err = crawler.run(&driver, &mut site).await.unwrap_err();
+    assert!(err.to_string().contains("session deleted"), "{}", err);
+    assert_eq!(crawler.visited(), 1);
+
+    // the page being visited is still first in the checkpoint
+    let mut crawler = Crawler::resume(config, &[]).unwrap();
+    std::fs::remove_file(&checkpoint).unwrap();
+    assert_eq!(crawler.visited(), 1);
+    assert_eq!(crawler.pending(), 2);
+    items.extend(crawler.run(&driver, &mut site).await.unwrap());
+    assert_eq!(items, ["/", "/ok"]);
+    assert_eq!(site.handled, ["/", "/broken", "/ok", "/broken"]);
+}
+
+#[tokio::test]
+async fn robots_txt_is_fetched_without_credentials() {
+    let (mock, driver) = setup().await;
+    let robots = "User-agent: *\nDisallow: /broken\n";
+    mock.on(
+        Method::POST,
+        "execute/async",
+        Reply::Value(json!({
+            "status": 200,
+            "headers": [],
+            "body": base64::engine::general_purpose::STANDARD.encode(robots),
+        })),
+    );
+    let config = CrawlConfig {
+        respect_robots: true,
+        ..config()
+    };
+    let mut crawler = Crawler::new(config, &["https://example.com/"]).unwrap();
+    let mut site = Site::default();
+    let items = crawler.run(&driver, &mut site).await.unwrap();
+    assert_eq!(items, ["/", "/ok"]);
+    assert!(crawler.failed().is_empty());
+
+    // fetched once, from the current page, which has the same origin
+    let fetches = mock.requests_to(Method::POST, "execute/async");
+    assert_eq!(fetches.len(), 1);
+    assert_eq!(fetches[0].body["args"][0], "https://example.com/robots.txt");
+    assert_eq!(fetches[0].body["args"][5], "omit");
+    let visited = mock
+        .requests_to(Method::POST, "url")
+        .into_iter()
+        .map(|r| r.body["url"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(visited, ["https://example.com/", "https://example.com/ok"]);
+}
+
+#[tokio::test]
+async fn robots_txt_of_another_origin() {
+    let (mock, driver) = setup().await;
+    // CORS blocks the fetch from https://example.com/
+    let blocked = Reply::Value(json!({"error": "TypeError"}));
+    mock.on(
+        Method::POST,
+        "execute/async",
+        Reply::Value(json!({"status": 404, "headers": [], "body": ""})),
+    )
+    .once(Method::POST, "execute/async", blocked);
+    let config = CrawlConfig {
+        respect_robots: true,
+        ..config()
+    };
+    let mut crawler = Crawler::new(config, &["https://other.com/"]).unwrap();
+    let mut site = Site::default();
+    crawler.step(&driver, &mut site).await.unwrap();
+    let visited = mock
+        .requests_to(Method::POST, "url")
+        .into_iter()
+        .map(|r| r.body["url"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(visited, ["https://other.com/robots.txt", "https://other.com/"]);
+    assert_eq!(mock.requests_to(Method::POST, "execute/async").len(), 2);
+}
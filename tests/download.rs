@@ -0,0 +1,55 @@
+use rusty_driver::testing::{Flavor, MockServer};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+fn download_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn stale_partial_files_are_ignored() {
+    let dir = download_dir("stale-partial");
+    // left over from a download that never finished
+    fs::write(dir.join("old.pdf.crdownload"), b"half").unwrap();
+    let driver = MockServer::new(Flavor::Chromedriver).driver().await.unwrap();
+    let action = async {
+        fs::write(dir.join("report.pdf"), b"%PDF").unwrap();
+        Ok(())
+    };
+    let file = driver
+        .expect_download(&dir, Duration::from_secs(5), action)
+        .await
+        .unwrap();
+    assert_eq!(file.path, dir.join("report.pdf"));
+    assert_eq!(file.bytes, b"%PDF");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn newest_download_wins() {
+    let dir = download_dir("newest-download");
+    fs::write(dir.join("unchanged.txt"), b"old").unwrap();
+    let driver = MockServer::new(Flavor::Geckodriver).driver().await.unwrap();
+    let action = async {
+        let now = SystemTime::now();
+        for (name, age) in [("b.csv", 1), ("a.csv", 30), ("c.csv", 20)] {
+            let path = dir.join(name);
+            fs::write(&path, name).unwrap();
+            let f = fs::File::options().write(true).open(&path).unwrap();
+            f.set_modified(now - Duration::from_secs(age)).unwrap();
+        }
+        Ok(())
+    };
+    let file = driver
+        .expect_download(&dir, Duration::from_secs(5), action)
+        .await
+        .unwrap();
+    assert_eq!(file.path, dir.join("b.csv"));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,681 @@
+use base64::Engine;
+use http::Method;
+use rusty_driver::{
+    error::*,
+    fetch::FetchOptions,
+    testing::{Flavor, MockServer, Reply},
+    Driver, Locator, PrintOptions,
+};
+use serde_json::{json, Value};
+use std::{path::Path, time::Duration};
+use webdriver::{common::WebElement, error::ErrorStatus};
+
+fn execute_path(flavor: Flavor) -> &'static str {
+    if flavor.is_legacy() {
+        "execute"
+    } else {
+        "execute/sync"
+    }
+}
+
+async fn setup(flavor: Flavor) -> (MockServer, Driver) {
+    let mock = MockServer::new(flavor);
+    let driver = mock.driver().await.unwrap();
+    (mock, driver)
+}
+
+#[tokio::test]
+async fn goto_resolves_relative_urls() {
+    for flavor in Flavor::ALL {
+        let (mock, driver) = setup(flavor).await;
+        mock.on(Method::GET, "url", Reply::Value(json!("https://example.com/a/b")))
+            .on(Method::POST, "url", Reply::Value(Value::Null));
+        driver.goto("../c?q=1").await.unwrap();
+        let reqs = mock.requests_to(Method::POST, "url");
+        assert_eq!(reqs[0].body, json!({"url": "https://example.com/c?q=1"}));
+        assert_eq!(
+            driver.current_url().await.unwrap().as_str(),
+            "https://example.com/a/b"
+        );
+    }
+}
+
+#[tokio::test]
+async fn navigation() {
+    for flavor in Flavor::ALL {
+        let (mock, driver) = setup(flavor).await;
+        for path in ["back", "forward", "refresh"] {
+            mock.on(Method::POST, path, Reply::Value(Value::Null));
+        }
+        mock.on(Method::GET, "title", Reply::Value(json!("Home")))
+            .on(Method::GET, "source", Reply::Value(json!("<html></html>")));
+        driver.back().await.unwrap();
+        driver.forward().await.unwrap();
+        driver.refresh().await.unwrap();
+        assert_eq!(driver.title().await.unwrap(), "Home");
+        assert_eq!(driver.source().await.unwrap(), "<html></html>");
+        let posted = mock
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == Method::POST && r.path != "session")
+            .map(|r| r.path)
+            .collect::<Vec<_>>();
+        assert_eq!(posted, ["back", "forward", "refresh"]);
+    }
+}
+
+#[tokio::test]
+async fn find_and_read_elements() {
+    for flavor in Flavor::ALL {
+        let (mock, driver) = setup(flavor).await;
+        mock.on(Method::POST, "element", Reply::Element("e1".into()))
+            .on(Method::POST, "elements", Reply::Elements(vec!["e1".into(), "e2".into()]))
+            .on(Method::POST, "element/e1/elements", Reply::Elements(vec![]))
+            .on(Method::GET, "element/*/text", Reply::Value(json!("Hello")))
+            .on(Method::GET, "element/e1/attribute/href", Reply::Value(json!("/x")))
+            .on(Method::GET, "element/e2/attribute/href", Reply::Value(Value::Null));
+        let e1 = driver.find(Locator::id("main"), None).await.unwrap();
+        assert_eq!(e1, WebElement("e1".into()));
+        let all = driver.find_all(Locator::Css("p".into()), None).await.unwrap();
+        assert_eq!(all.len(), 2);
+        let none = driver.find_all(Locator::TagName("a".into()), Some(e1.clone()));
+        assert!(none.await.unwrap().is_empty());
+        assert_eq!(driver.text(all[1].clone()).await.unwrap(), "Hello");
+        let href = driver.attr(e1.clone(), "href".into()).await.unwrap();
+        assert_eq!(href.as_deref(), Some("/x"));
+        assert_eq!(driver.attr(all[1].clone(), "href".into()).await.unwrap(), None);
+
+        let find = &mock.requests_to(Method::POST, "element")[0];
+        assert_eq!(find.body, json!({"using": "css selector", "value": "#main"}));
+        let nested = &mock.requests_to(Method::POST, "element/e1/elements")[0];
+        assert_eq!(nested.body, json!({"using": "tag name", "value": "a"}));
+    }
+}
+
+#[tokio::test]
+async fn find_missing_element() {
+    for flavor in Flavor::ALL {
+        let (mock, driver) = setup(flavor).await;
+        mock.on(
+            Method::POST,
+            "element",
+            Reply::Error(ErrorStatus::NoSuchElement, "not found".into()),
+        );
+        match driver.find(Locator::Css("nope".into()), None).await {
+            Err(Error(ErrorKind::WebDriver(e), _)) => {
+                assert_eq!(e.error, ErrorStatus::NoSuchElement)
+            }
+            r => panic!("{:?}: unexpected {:?}", flavor, r),
+        }
+    }
+}
+
+#[tokio::test]
+async fn wait_for_find_polls() {
+    for flavor in Flavor::ALL {
+        let (mock, driver) = setup(flavor).await;
+        let missing = || Reply::Error(ErrorStatus::NoSuchElement, "not yet".into());
+        mock.once(Method::POST, "element", missing())
+            .once(Method::POST, "element", missing())
+            .once(Method::POST, "element", Reply::Element("e1".into()));
+        let elt = driver.wait_for_find(Locator::Css("p".into()), None).await.unwrap();
+        assert_eq!(elt, WebElement("e1".into()));
+        assert_eq!(mock.requests_to(Method::POST, "element").len(), 3);
+        assert_eq!(mock.slept(), Duration::from_millis(200));
+    }
+}
+
+#[tokio::test]
+async fn execute_converts_element_references() {
+    for flavor in Flavor::ALL {
+        let (mock, driver) = setup(flavor).await;
+        let key = if flavor.is_legacy() {
+            "ELEMENT"
+        } else {
+            "element-6066-11e4-a52e-4f735466cecf"
+        };
+        mock.on(
+            Method::POST,
+            execute_path(flavor),
+            Reply::Value(json!(["T", [{ key: "e2" }]])),
+        );
+        let body = WebElement("e1".into());
+        let (title, links): (String, Vec<WebElement>) = driver
+            .execute_typed("return 1".into(), (&body, 3))
+            .await
+            .unwrap();
+        assert_eq!(title, "T");
+        assert_eq!(links, vec![WebElement("e2".into())]);
+        let req = &mock.requests_to(Method::POST, execute_path(flavor))[0];
+        assert_eq!(req.body["script"], "return 1");
+        assert_eq!(req.body["args"], json!([{ key: "e1" }, 3]));
+    }
+}
+
+#[tokio::test]
+async fn execute_async_and_fetch() {
+    for flavor in Flavor::ALL {
+        let (mock, driver) = setup(flavor).await;
+        let path = if flavor.is_legacy() {
+            "execute_async"
+        } else {
+            "execute/async"
+        };
+        let body = base64::engine::general_purpose::STANDARD.encode("hello");
+        mock.on(Method::GET, "url", Reply::Value(json!("https://example.com/")))
+            .on(
+                Method::POST,
+                path,
+                Reply::Value(json!({
+                    "status": 200,
+                    "headers": [["content-type", "text/plain"]],
+                    "body": body,
+                })),
+            );
+        let res = driver.fetch("/data", FetchOptions::default()).await.unwrap();
+        assert_eq!(res.status, 200);
+        assert_eq!(res.header("Content-Type"), Some("text/plain"));
+        assert_eq!(res.body, b"hello");
+        let req = &mock.requests_to(Method::POST, path)[0];
+        assert_eq!(req.body["args"][0], "https://example.com/data");
+        assert_eq!(req.body["args"][1], "GET");
+        assert_eq!(req.body["args"][5], "include");
+
+        mock.on(Method::POST, path, Reply::Value(json!({"error": "TypeError"})));
+        let err = driver.fetch("/data", FetchOptions::default()).await.unwrap_err();
+        assert!(err.to_string().contains("TypeError"), "{}", err);
+    }
+}
+
+#[tokio::test]
+async fn print_pdf() {
+    let (mock, driver) = setup(Flavor::Chromedriver).await;
+    let pdf = base64::engine::general_purpose::STANDARD.encode("%PDF-1.4");
+    mock.on(Method::POST, "print", Reply::Value(json!(pdf)));
+    let options = PrintOptions {
+        background: true,
+        ..PrintOptions::default()
+    };
+    assert_eq!(driver.print_pdf(options).await.unwrap(), b"%PDF-1.4");
+    let req = &mock.requests_to(Method::POST, "print")[0];
+    assert_eq!(req.body["background"], true);
+}
+
+#[tokio::test]
+async fn windows() {
+    for flavor in [Flavor::Geckodriver, Flavor::Chromedriver] {
+        let (mock, driver) = setup(flavor).await;
+        mock.on(
+            Method::GET,
+            "window/handles",
+            Reply::Value(json!(["window-1", "window-2"])),
+        )
+        .on(Method::POST, "window", Reply::Value(Value::Null))
+        .on(Method::DELETE, "window", Reply::Value(json!(["window-1"])));
+        assert_eq!(driver.window_handles().await.unwrap(), ["window-1", "window-2"]);
+        driver.switch_to_window("window-2".into()).await.unwrap();
+        driver.close_window().await.unwrap();
+        driver.switch_to_window("window-1".into()).await.unwrap();
+        let switches = mock.requests_to(Method::POST, "window");
+        assert_eq!(switches[0].body, json!({"handle": "window-2"}));
+        assert_eq!(switches[1].body, json!({"handle": "window-1"}));
+    }
+}
+
+#[tokio::test]
+async fn clones_keep_their_own_frame() {
+    let (mock, a) = setup(Flavor::Geckodriver).await;
+    let b = a.clone();
+    mock.on(Method::POST, "frame", Reply::Value(Value::Null))
+        .on(Method::POST, "frame/parent", Reply::Value(Value::Null))
+        .on(Method::POST, "window", Reply::Value(Value::Null))
+        .on(Method::GET, "title", Reply::Value(json!("T")));
+    a.switch_to_frame(WebElement("f1".into())).await.unwrap();
+    let start = mock.requests().len();
+    // b is still in the top level context, so it must switch back
+    b.title().await.unwrap();
+    // and a must switch into its frame again
+    a.title().await.unwrap();
+    // no switching needed
+    a.title().await.unwrap();
+    let paths = mock.requests()[start..]
+        .iter()
+        .map(|r| (r.path.clone(), r.body.clone()))
+        .collect::<Vec<_>>();
+    let frame = json!({"id": {"element-6066-11e4-a52e-4f735466cecf": "f1"}});
+    assert_eq!(
+        paths,
+        vec![
+            ("window".to_string(), json!({"handle": "window-1"})),
+            ("frame".to_string(), json!({"id": null})),
+            ("title".to_string(), Value::Null),
+            ("window".to_string(), json!({"handle": "window-1"})),
+            ("frame".to_string(), json!({"id": null})),
+            ("frame".to_string(), frame),
+            ("title".to_string(), Value::Null),
+            ("title".to_string(), Value::Null),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn shadow_roots() {
+    let (mock, driver) = setup(Flavor::Chromedriver).await;
+    mock.on(
+        Method::GET,
+        "element/host/shadow",
+        Reply::Value(json!({"shadow-6066-11e4-a52e-4f735466cecf": "s1"})),
+    )
+    .on(Method::POST, "shadow/s1/element", Reply::Element("inner".into()));
+    let root = driver.shadow_root(WebElement("host".into())).await.unwrap();
+    assert_eq!(root.id(), "s1");
+    let elt = root.find(Locator::Css("button".into())).await.unwrap();
+    assert_eq!(elt, WebElement("inner".into()));
+}
+
+#[tokio::test]
+async fn accessibility_properties() {
+    let (mock, driver) = setup(Flavor::Chromedriver).await;
+    mock.on(Method::POST, "elements", Reply::Elements(vec!["b1".into(), "b2".into()]))
+        .on(Method::GET, "element/*/computedrole", Reply::Value(json!("button")))
+        .on(Method::GET, "element/b1/computedlabel", Reply::Value(json!("Cancel")))
+        .on(Method::GET, "element/b2/computedlabel", Reply::Value(json!(" Save ")));
+    let save = driver.find_by_role("button", Some("Save"), None).await.unwrap();
+    assert_eq!(save, WebElement("b2".into()));
+    match driver.find_by_role("button", Some("Delete"), None).await {
+        Err(Error(ErrorKind::WebDriver(e), _)) => {
+            assert_eq!(e.error, ErrorStatus::NoSuchElement)
+        }
+        r => panic!("unexpected {:?}", r),
+    }
+}
+
+#[tokio::test]
+async fn press_keys() {
+    let (mock, driver) = setup(Flavor::Geckodriver).await;
+    mock.on(Method::POST, "actions", Reply::Value(Value::Null))
+        .on(Method::DELETE, "actions", Reply::Value(Value::Null));
+    driver.press_keys("ab").await.unwrap();
+    let req = &mock.requests_to(Method::POST, "actions")[0];
+    let actions = &req.body["actions"][0];
+    assert_eq!(actions["type"], "key");
+    assert_eq!(actions["actions"].as_array().unwrap().len(), 4);
+    assert_eq!(actions["actions"][0], json!({"type": "keyDown", "value": "a"}));
+    assert_eq!(mock.requests_to(Method::DELETE, "actions").len(), 1);
+}
+
+#[tokio::test]
+async fn upload_falls_back_to_local_paths() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    let local = path.canonicalize().unwrap();
+    let (mock, driver) = setup(Flavor::Geckodriver).await;
+    mock.on(Method::POST, "element/in/value", Reply::Value(Value::Null));
+    driver.upload_file(WebElement("in".into()), &path).await.unwrap();
+    assert_eq!(mock.requests_to(Method::POST, "se/file").len(), 1);
+    let req = &mock.requests_to(Method::POST, "element/in/value")[0];
+    assert_eq!(req.body["text"], local.to_string_lossy().as_ref());
+
+    // a server with the upload extension gets the file contents
+    mock.on(Method::POST, "se/file", Reply::Value(json!("/remote/Cargo.toml")));
+    driver.upload_file(WebElement("in".into()), &path).await.unwrap();
+    let upload = &mock.requests_to(Method::POST, "se/file")[1];
+    assert!(upload.body["file"].as_str().unwrap().len() > 100);
+    let req = &mock.requests_to(Method::POST, "element/in/value")[1];
+    assert_eq!(req.body["text"], "/remote/Cargo.toml");
+}
+
+#[tokio::test]
+async fn delete_cookies() {
+    for flavor in Flavor::ALL {
+        let (mock, driver) = setup(flavor).await;
+        mock.on(Method::DELETE, "cookie", Reply::Value(Value::Null));
+        driver.delete_all_cookies().await.unwrap();
+        assert_eq!(mock.requests_to(Method::DELETE, "cookie").len(), 1);
+    }
+}
+
+#[tokio::test]
+async fn fill_form() {
+    #[derive(serde::Serialize)]
+    struct Login {
+        user: String,
+        remember: bool,
+        otp: Option<String>,
+    }
+    let login = Login {
+        user: "ann".into(),
+        remember: true,
+        otp: None,
+    };
+    let (mock, driver) = setup(Flavor::Geckodriver).await;
+    let form = WebElement("f1".into());
+    let result = |missing: Value, invalid: Value| {
+        Reply::Value(json!({"missing": missing, "invalid": invalid}))
+    };
+    mock.on(Method::POST, "execute/sync", result(json!([]), json!([])));
+    driver.fill_form(form.clone(), &login).await.unwrap();
+    let req = &mock.requests_to(Method::POST, "execute/sync")[0];
+    // a None leaves the control alone
+    assert_eq!(req.body["args"][1], json!({"user": "ann", "remember": true}));
+
+    mock.on(Method::POST, "execute/sync", result(json!(["user", "otp"]), json!([])));
+    let err = driver.fill_form(form.clone(), &login).await.unwrap_err();
+    assert_eq!(err.to_string(), "no form control named user, otp");
+
+    mock.on(Method::POST, "execute/sync", result(json!([]), json!(["remember"])));
+    let err = driver.fill_form(form.clone(), &login).await.unwrap_err();
+    assert_eq!(err.to_string(), "invalid value for form control remember");
+
+    let err = driver.fill_form(form, &["not", "a", "map"]).await.unwrap_err();
+    assert!(err.to_string().starts_with("form values must serialize to a map"));
+}
+
+#[tokio::test]
+async fn links_and_resources() {
+    use rusty_driver::extract::{Link, Resource, ResourceKind};
+    let (mock, driver) = setup(Flavor::Geckodriver).await;
+    let link = |href: &str, rel: Value| {
+        json!({"href": href, "text": "t", "rel": rel, "target": null})
+    };
+    mock.on(
+        Method::POST,
+        "execute/sync",
+        Reply::Value(json!({
+            "base": "http://example.com/docs/",
+            "links": [
+                link(" page.html ", json!("NoFollow  external")),
+                link("/top", Value::Null),
+                link("http://[::1", json!("next")),
+            ],
+        })),
+    );
+    let links = driver.links().await.unwrap();
+    let expect = |url: &str, rel: &[&str]| Link {
+        url: url.parse().unwrap(),
+        text: "t".into(),
+        rel: rel.iter().map(|r| r.to_string()).collect(),
+        target: None,
+    };
+    // resolved against the base, not the current url, and the
+    // unresolvable href is dropped
+    assert_eq!(
+        links,
+        vec![
+            expect("http://example.com/docs/page.html", &["nofollow", "external"]),
+            expect("http://example.com/top", &[]),
+        ]
+    );
+    assert!(mock.requests_to(Method::GET, "url").is_empty());
+
+    mock.on(
+        Method::POST,
+        "execute/sync",
+        Reply::Value(json!({
+            "base": "https://example.com/a/b",
+            "resources": [
+                {"kind": "script", "src": "app.js"},
+                {"kind": "image", "src": "//cdn.example.com/i.png"},
+                {"kind": "iframe", "src": "http://[::1"},
+            ],
+        })),
+    );
+    let resources = driver.resources().await.unwrap();
+    assert_eq!(
+        resources,
+        vec![
+            Resource {
+                kind: ResourceKind::Script,
+                url: "https://example.com/a/app.js".parse().unwrap(),
+            },
+            Resource {
+                kind: ResourceKind::Image,
+                url: "https://cdn.example.com/i.png".parse().unwrap(),
+            },
+        ]
+    );
+
+    mock.on(
+        Method::POST,
+        "execute/sync",
+        Reply::Value(json!({"base": "about:blank", "links": [link("x", Value::Null)]})),
+    );
+    assert!(driver.links().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn extract() {
+    use rusty_driver::extract::Extract;
+    #[derive(serde::Deserialize, PartialEq, Debug)]
+    struct Row {
+        text: String,
+        href: Option<String>,
+        el: WebElement,
+    }
+    let (mock, driver) = setup(Flavor::Geckodriver).await;
+    let key = "element-6066-11e4-a52e-4f735466cecf";
+    mock.on(
+        Method::POST,
+        "execute/sync",
+        Reply::Value(json!([
+            {"text": "One", "href": "/1", "el": {key: "e1"}},
+            {"text": "Two", "href": null, "el": {key: "e2"}},
+        ])),
+    );
+    let spec = Extract::new().text("text").attr("href", "href").element("el");
+    let root = WebElement("r1".into());
+    let rows: Vec<Row> = driver
+        .extract(Locator::Css("a".into()), Some(root), &spec)
+        .await
+        .unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            Row {
+                text: "One".into(),
+                href: Some("/1".into()),
+                el: WebElement("e1".into()),
+            },
+            Row {
+                text: "Two".into(),
+                href: None,
+                el: WebElement("e2".into()),
+            },
+        ]
+    );
+    let req = &mock.requests_to(Method::POST, "execute/sync")[0];
+    assert_eq!(
+        req.body["args"],
+        json!([
+            {"using": "css selector", "value": "a"},
+            {key: "r1"},
+            [
+                {"field": "text", "source": "text", "name": null},
+                {"field": "href", "source": "attr", "name": "href"},
+                {"field": "el", "source": "element", "name": null},
+            ],
+        ])
+    );
+}
+
+#[tokio::test]
+async fn goto_and_wait() {
+    use rusty_driver::ReadyState;
+    let (mock, driver) = setup(Flavor::Geckodriver).await;
+    mock.on(Method::GET, "url", Reply::Value(json!("https://example.com/")))
+        .on(Method::POST, "url", Reply::Value(Value::Null));
+    for state in ["loading", "interactive", "complete"] {
+        mock.once(Method::POST, "execute/sync", Reply::Value(json!(state)));
+    }
+    mock.once(
+        Method::POST,
+        "execute/sync",
+        Reply::Value(json!({
+            "name": "https://example.com/a",
+            "type": "navigate",
+            "duration": 12.5,
+            "redirectCount": 1,
+            "domComplete": 11.0,
+            "serverTiming": [],
+        })),
+    );
+    let timing = driver.goto_and_wait("a", ReadyState::Complete).await.unwrap();
+    assert_eq!(timing.name, "https://example.com/a");
+    assert_eq!(timing.navigation_type, "navigate");
+    assert_eq!(timing.duration, 12.5);
+    assert_eq!(timing.redirect_count, 1);
+    assert_eq!(timing.dom_complete, 11.0);
+    assert_eq!(timing.load_event_end, 0.0);
+    // polled until complete, sleeping between polls
+    assert_eq!(mock.requests_to(Method::POST, "execute/sync").len(), 4);
+    assert_eq!(mock.slept(), Duration::from_millis(200));
+
+    mock.once(Method::POST, "execute/sync", Reply::Value(json!("interactive")))
+        .once(Method::POST, "execute/sync", Reply::Value(Value::Null));
+    let err = driver
+        .goto_and_wait("a", ReadyState::Interactive)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "no navigation timing entry available");
+    assert_eq!(mock.slept(), Duration::from_millis(200));
+}
+
+#[tokio::test]
+async fn tab_order() {
+    let (mock, driver) = setup(Flavor::Geckodriver).await;
+    mock.on(Method::POST, "actions", Reply::Value(Value::Null))
+        .on(Method::DELETE, "actions", Reply::Value(Value::Null));
+    for id in ["e1", "e2", "e3", "e1"] {
+        mock.once(Method::GET, "element/active", Reply::Element(id.into()));
+    }
+    let order = driver.tab_order(10).await.unwrap();
+    let elts = |ids: &[&str]| -> Vec<WebElement> {
+        ids.iter().map(|id| WebElement(id.to_string())).collect()
+    };
+    // stops once focus wraps around to e1
+    assert_eq!(order, elts(&["e1", "e2", "e3"]));
+    let presses = mock.requests_to(Method::POST, "actions");
+    assert_eq!(presses.len(), 4);
+    assert_eq!(
+        presses[0].body["actions"][0]["actions"][0],
+        json!({"type": "keyDown", "value": "\u{E004}"})
+    );
+    assert_eq!(mock.requests_to(Method::DELETE, "actions").len(), 4);
+
+    for id in ["e1", "e2", "e3"] {
+        mock.once(Method::GET, "element/active", Reply::Element(id.into()));
+    }
+    assert_eq!(driver.tab_order(2).await.unwrap(), elts(&["e1", "e2"]));
+    assert_eq!(mock.requests_to(Method::POST, "actions").len(), 6);
+}
+
+#[tokio::test]
+async fn form_values() {
+    #[derive(serde::Deserialize, PartialEq, Debug)]
+    struct Search {
+        q: String,
+        lang: Vec<String>,
+        safe: bool,
+        page: Option<u32>,
+    }
+    let (mock, driver) = setup(Flavor::Geckodriver).await;
+    let key = "element-6066-11e4-a52e-4f735466cecf";
+    mock.on(
+        Method::POST,
+        "execute/sync",
+        Reply::Value(json!([
+            ["q", "rust"],
+            ["lang", "en"],
+            ["lang", "de"],
+            ["safe", "on"],
+        ])),
+    );
+    let form = WebElement("f1".into());
+    let values = driver.form_values(form.clone(), None).await.unwrap();
+    assert_eq!(values.get("q"), Some("rust"));
+    assert_eq!(values.get_all("lang").collect::<Vec<_>>(), ["en", "de"]);
+    assert_eq!(
+        values.deserialize::<Search>().unwrap(),
+        Search {
+            q: "rust".into(),
+            lang: vec!["en".into(), "de".into()],
+            safe: true,
+            page: None,
+        }
+    );
+    assert_eq!(values.to_urlencoded(), "q=rust&lang=en&lang=de&safe=on");
+
+    let submitter = WebElement("b1".into());
+    driver.form_values(form, Some(submitter)).await.unwrap();
+    let reqs = mock.requests_to(Method::POST, "execute/sync");
+    assert_eq!(reqs[0].body["args"], json!([{ key: "f1" }, null]));
+    assert_eq!(reqs[1].body["args"], json!([{ key: "f1" }, { key: "b1" }]));
+}
+
+#[tokio::test]
+async fn select() {
+    use rusty_driver::form::Select;
+    let (mock, driver) = setup(Flavor::Geckodriver).await;
+    let key = "element-6066-11e4-a52e-4f735466cecf";
+    let select = Select::new(&driver, WebElement("s1".into()));
+    let ok = || Reply::Value(json!(true));
+    mock.once(Method::POST, "execute/sync", ok())
+        .once(Method::POST, "execute/sync", ok())
+        .once(Method::POST, "execute/sync", ok());
+    select.select_by_value("b").await.unwrap();
+    select.select_by_visible_text("  Two \n Words ").await.unwrap();
+    select.select_by_index(2).await.unwrap();
+    let args: Vec<Value> = mock
+        .requests_to(Method::POST, "execute/sync")
+        .into_iter()
+        .map(|r| r.body["args"].clone())
+        .collect();
+    assert_eq!(
+        args,
+        [
+            json!([{ key: "s1" }, "value", "b", true]),
+            json!([{ key: "s1" }, "text", "Two Words", true]),
+            json!([{ key: "s1" }, "index", 2, true]),
+        ]
+    );
+
+    mock.once(Method::POST, "execute/sync", Reply::Value(json!(false)));
+    match select.select_by_value("zz").await {
+        Err(Error(ErrorKind::WebDriver(e), _)) => {
+            assert_eq!(e.error, ErrorStatus::NoSuchElement);
+            assert_eq!(e.message, "no option with value \"zz\"");
+        }
+        r => panic!("unexpected {:?}", r),
+    }
+
+    // a single select can't have its options deselected, and is left
+    // untouched
+    mock.once(Method::POST, "execute/sync", Reply::Value(json!(false)))
+        .once(Method::POST, "execute/sync", Reply::Value(json!(false)));
+    let err = select.deselect_by_value("b").await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "only the options of a multi select can be deselected"
+    );
+    assert!(select.deselect_all().await.is_err());
+    assert_eq!(mock.requests_to(Method::POST, "execute/sync").len(), 6);
+
+    mock.once(Method::POST, "execute/sync", Reply::Value(json!(true)))
+        .once(Method::POST, "execute/sync", ok());
+    select.deselect_by_value("b").await.unwrap();
+    let req = mock.requests_to(Method::POST, "execute/sync").pop().unwrap();
+    assert_eq!(req.body["args"], json!([{ key: "s1" }, "value", "b", false]));
+
+    mock.on(Method::POST, "execute/sync", Reply::Elements(vec!["o2".into()]))
+        .on(
+            Method::POST,
+            "element/s1/elements",
+            Reply::Elements(vec!["o1".into(), "o2".into()]),
+        );
+    assert_eq!(select.selected().await.unwrap(), [WebElement("o2".into())]);
+    assert_eq!(
+        select.options().await.unwrap(),
+        [WebElement("o1".into()), WebElement("o2".into())]
+    );
+    let req = mock.requests_to(Method::POST, "element/s1/elements").pop().unwrap();
+    assert_eq!(req.body, json!({"using": "tag name", "value": "option"}));
+}
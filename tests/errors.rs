@@ -0,0 +1,138 @@
+use http::{Method, StatusCode};
+use rusty_driver::{
+    error::*,
+    testing::{Flavor, MockServer, Reply},
+};
+use serde_json::json;
+use webdriver::error::ErrorStatus;
+
+fn statuses() -> Vec<ErrorStatus> {
+    use ErrorStatus::*;
+    vec![
+        ElementClickIntercepted,
+        ElementNotInteractable,
+        ElementNotSelectable,
+        InsecureCertificate,
+        InvalidArgument,
+        InvalidCookieDomain,
+        InvalidCoordinates,
+        InvalidElementState,
+        InvalidSelector,
+        InvalidSessionId,
+        JavascriptError,
+        MoveTargetOutOfBounds,
+        NoSuchAlert,
+        NoSuchCookie,
+        NoSuchElement,
+        NoSuchFrame,
+        NoSuchWindow,
+        ScriptTimeout,
+        SessionNotCreated,
+        StaleElementReference,
+        Timeout,
+        UnableToCaptureScreen,
+        UnableToSetCookie,
+        UnexpectedAlertOpen,
+        UnknownCommand,
+        UnknownError,
+        UnknownMethod,
+        UnsupportedOperation,
+    ]
+}
+
+/// The legacy protocol has no code for some errors, servers report
+/// them as unknown errors
+fn legacy_status(status: ErrorStatus) -> ErrorStatus {
+    use ErrorStatus::*;
+    match status {
+        ElementClickIntercepted | InsecureCertificate | InvalidArgument
+        | InvalidSessionId | NoSuchCookie | UnableToCaptureScreen | UnknownMethod
+        | UnsupportedOperation => UnknownError,
+        s => s,
+    }
+}
+
+#[tokio::test]
+async fn error_decoding() {
+    for flavor in Flavor::ALL {
+        let mock = MockServer::new(flavor);
+        let driver = mock.driver().await.unwrap();
+        // ErrorStatus isn't Clone
+        for (status, expected) in statuses().into_iter().zip(statuses()) {
+            let code = status.error_code();
+            let expected = if flavor.is_legacy() {
+                legacy_status(expected)
+            } else {
+                expected
+            };
+            mock.on(Method::GET, "title", Reply::Error(status, "oops".into()));
+            match driver.title().await {
+                Err(Error(ErrorKind::WebDriver(e), _)) => {
+                    assert_eq!(e.error, expected, "{:?} {}", flavor, code);
+                    assert_eq!(e.message, "oops");
+                }
+                r => panic!("{:?} {}: unexpected {:?}", flavor, code, r),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn unscripted_command() {
+    for flavor in Flavor::ALL {
+        let mock = MockServer::new(flavor);
+        let driver = mock.driver().await.unwrap();
+        match driver.source().await {
+            Err(Error(ErrorKind::WebDriver(e), _)) => {
+                assert_eq!(e.error, ErrorStatus::UnknownCommand)
+            }
+            r => panic!("{:?}: unexpected {:?}", flavor, r),
+        }
+    }
+}
+
+#[tokio::test]
+async fn malformed_responses() {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    let driver = mock.driver().await.unwrap();
+    let raw = |status, content_type: Option<&str>, body: &str| Reply::Raw {
+        status,
+        content_type: content_type.map(String::from),
+        body: body.into(),
+    };
+
+    mock.once(Method::GET, "title", raw(StatusCode::OK, Some("text/html"), "<html>"));
+    match driver.title().await {
+        Err(Error(ErrorKind::NotJson(Some(c)), _)) => assert_eq!(c, "text/html"),
+        r => panic!("unexpected {:?}", r),
+    }
+
+    mock.once(Method::GET, "title", raw(StatusCode::OK, None, "{}"));
+    match driver.title().await {
+        Err(Error(ErrorKind::NotJson(None), _)) => (),
+        r => panic!("unexpected {:?}", r),
+    }
+
+    // no "value"
+    mock.once(Method::GET, "title", raw(StatusCode::OK, Some("application/json"), "{}"));
+    match driver.title().await {
+        Err(Error(ErrorKind::NotW3C(v), _)) => assert_eq!(v, json!({})),
+        r => panic!("unexpected {:?}", r),
+    }
+
+    // an error without a message
+    let body = r#"{"value": {"error": "no such element"}}"#;
+    let reply = raw(StatusCode::NOT_FOUND, Some("application/json"), body);
+    mock.once(Method::GET, "title", reply);
+    match driver.title().await {
+        Err(Error(ErrorKind::NotW3C(_), _)) => (),
+        r => panic!("unexpected {:?}", r),
+    }
+
+    // a success with the wrong type
+    mock.once(Method::GET, "title", Reply::Value(json!(42)));
+    match driver.title().await {
+        Err(Error(ErrorKind::NotW3C(v), _)) => assert_eq!(v, json!(42)),
+        r => panic!("unexpected {:?}", r),
+    }
+}
@@ -0,0 +1,166 @@
+use futures::FutureExt;
+use http::Method;
+use rusty_driver::{
+    pool::{DriverPool, DriverPoolBuilder},
+    testing::{Flavor, MockServer, Reply},
+    Driver,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use webdriver::error::ErrorStatus;
+
+/// A mock server with the commands of the health check and the reset
+/// scripted
+fn mock() -> MockServer {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    mock.on(Method::GET, "url", Reply::Value(json!("https://example.com/")))
+        .on(Method::POST, "url", Reply::Value(Value::Null))
+        .on(Method::GET, "window/handles", Reply::Value(json!(["window-1"])))
+        .on(Method::POST, "window", Reply::Value(Value::Null))
+        .on(Method::DELETE, "cookie", Reply::Value(Value::Null))
+        .on(Method::POST, "execute/sync", Reply::Value(Value::Null));
+    mock
+}
+
+fn pool(mock: &MockServer) -> DriverPoolBuilder {
+    DriverPool::builder().transport(Arc::new(mock.clone()))
+}
+
+fn created(mock: &MockServer) -> usize {
+    mock.requests_to(Method::POST, "session").len()
+}
+
+fn closed(mock: &MockServer) -> usize {
+    mock.requests_to(Method::DELETE, "").len()
+}
+
+/// Let the tasks the pool spawned run
+async fn settle() {
+    for _ in 0..100 {
+        tokio::task::yield_now().await
+    }
+}
+
+#[tokio::test]
+async fn size_limits() {
+    let mock = mock();
+    let pool = pool(&mock).endpoint(MockServer::URL, 2).build();
+    let a = pool.get().await.unwrap();
+    let _b = pool.get().await.unwrap();
+    assert!(pool.get().now_or_never().is_none());
+    a.release().await;
+    let _c = pool.get().await.unwrap();
+    assert_eq!(created(&mock), 2);
+
+    let pool = self::pool(&mock).endpoint(MockServer::URL, 2).max_size(1).build();
+    let _a = pool.get().await.unwrap();
+    assert!(pool.get().now_or_never().is_none());
+
+    // the limit of each endpoint, the pool's is their sum
+    let pool = self::pool(&mock)
+        .endpoint(MockServer::URL, 1)
+        .endpoint("http://other.mock/", 1)
+        .build();
+    let _a = pool.get().await.unwrap();
+    let _b = pool.get().await.unwrap();
+    assert!(pool.get().now_or_never().is_none());
+}
+
+#[tokio::test]
+async fn sessions_are_reset_and_reused() {
+    let mock = mock();
+    let pool = pool(&mock).endpoint(MockServer::URL, 2).build();
+    let a = pool.get().await.unwrap();
+    a.goto("/page").await.unwrap();
+    a.release().await;
+    assert_eq!(mock.requests_to(Method::DELETE, "cookie").len(), 1);
+    let visited = mock.requests_to(Method::POST, "url");
+    assert_eq!(visited.last().unwrap().body["url"], "about:blank");
+
+    // dropped instead of released
+    drop(pool.get().await.unwrap());
+    settle().await;
+    assert_eq!(mock.requests_to(Method::DELETE, "cookie").len(), 2);
+    let _a = pool.get().await.unwrap();
+    assert_eq!(created(&mock), 1);
+    assert_eq!(closed(&mock), 0);
+}
+
+#[tokio::test]
+async fn worn_out_sessions_are_closed() {
+    let mock = mock();
+    let pool = pool(&mock).endpoint(MockServer::URL, 1).max_uses(2).build();
+    for _ in 0..2 {
+        pool.get().await.unwrap().release().await;
+    }
+    settle().await;
+    assert_eq!((created(&mock), closed(&mock)), (1, 1));
+    let _a = pool.get().await.unwrap();
+    assert_eq!(created(&mock), 2);
+}
+
+#[tokio::test]
+async fn unhealthy_sessions_are_replaced() {
+    let mock = mock();
+    let pool = pool(&mock).endpoint(MockServer::URL, 1).build();
+    pool.get().await.unwrap().release().await;
+    let gone = Reply::Error(ErrorStatus::InvalidSessionId, "session deleted".into());
+    mock.once(Method::GET, "url", gone);
+    let _a = pool.get().await.unwrap();
+    settle().await;
+    assert_eq!((created(&mock), closed(&mock)), (2, 1));
+}
+
+#[tokio::test]
+async fn busy_servers_are_healthy() {
+    let mock = mock();
+    let pool = pool(&mock).endpoint(MockServer::URL, 1).build();
+    pool.get().await.unwrap().release().await;
+    // like geckodriver while a session is open
+    let status = json!({"ready": false, "message": "Session already started"});
+    mock.on(Method::GET, "status", Reply::Value(status));
+    let _a = pool.get().await.unwrap();
+    settle().await;
+    assert_eq!((created(&mock), closed(&mock)), (1, 0));
+}
+
+#[tokio::test]
+async fn idle_sessions_are_kept_ready() {
+    let mock = mock();
+    let pool = pool(&mock).endpoint(MockServer::URL, 3).min_size(2).build();
+    pool.warm_up().await.unwrap();
+    assert_eq!(created(&mock), 2);
+
+    // replaced as it is checked out
+    let a = pool.get().await.unwrap();
+    settle().await;
+    assert_eq!(created(&mock), 3);
+
+    // the endpoint is full, so the replacement waits for a session
+    // to be closed
+    let _b = pool.get().await.unwrap();
+    settle().await;
+    assert_eq!(created(&mock), 3);
+    a.discard();
+    settle().await;
+    assert_eq!((created(&mock), closed(&mock)), (4, 1));
+    let _c = pool.get().await.unwrap();
+    let _d = pool.get().await.unwrap();
+    assert_eq!(created(&mock), 4);
+}
+
+#[tokio::test]
+async fn sessions_with_clones_are_not_reused() {
+    let mock = mock();
+    let pool = pool(&mock).endpoint(MockServer::URL, 1).build();
+    let a = pool.get().await.unwrap();
+    let clone = Driver::clone(&a);
+    a.release().await;
+    assert!(mock.requests_to(Method::GET, "window/handles").is_empty());
+    let _b = pool.get().await.unwrap();
+    assert_eq!(created(&mock), 2);
+    assert_eq!(closed(&mock), 0);
+    drop(clone);
+    settle().await;
+    assert_eq!(closed(&mock), 1);
+}
@@ -0,0 +1,162 @@
+use http::Method;
+use futures::future::BoxFuture;
+use rusty_driver::{
+    error::*,
+    testing::{Flavor, MockServer, Reply},
+    transport::{Request, Response, Transport},
+    Driver,
+};
+use serde_json::json;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use webdriver::{capabilities::Capabilities, error::ErrorStatus};
+
+#[tokio::test]
+async fn w3c_session() {
+    for flavor in [Flavor::Geckodriver, Flavor::Chromedriver] {
+        let mock = MockServer::new(flavor);
+        let _driver = mock.driver().await.unwrap();
+        let reqs = mock.requests();
+        assert_eq!(reqs[0].path, "session");
+        assert_eq!(
+            reqs[0].body["capabilities"]["alwaysMatch"]["pageLoadStrategy"],
+            "normal"
+        );
+        assert!(reqs[0].body.get("desiredCapabilities").is_none());
+        // the initial window is pinned when the session is created
+        assert_eq!(reqs[1].path, "window");
+        assert_eq!(reqs.len(), 2);
+    }
+}
+
+#[tokio::test]
+async fn legacy_fallback() {
+    for flavor in [Flavor::LegacyChromedriver, Flavor::PhantomJs] {
+        let mock = MockServer::new(flavor);
+        let driver = mock.driver().await.unwrap();
+        let reqs = mock.requests_to(Method::POST, "session");
+        assert_eq!(reqs.len(), 2, "{:?}", flavor);
+        assert!(reqs[0].body.get("capabilities").is_some());
+        assert_eq!(
+            reqs[1].body["requiredCapabilities"]["pageLoadStrategy"],
+            "normal"
+        );
+        assert_eq!(mock.requests_to(Method::GET, "window_handle").len(), 1);
+        // legacy endpoints are used from then on
+        mock.on(Method::POST, "execute", Reply::Value(json!(2)));
+        let v = driver.execute("return 1 + 1".into(), vec![]).await.unwrap();
+        assert_eq!(v, json!(2));
+    }
+}
+
+#[tokio::test]
+async fn capabilities_are_sent() {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    let mut caps = Capabilities::new();
+    caps.insert("pageLoadStrategy".into(), json!("eager"));
+    caps.insert("moz:firefoxOptions".into(), json!({"args": ["-headless"]}));
+    let transport = Arc::new(mock.clone());
+    Driver::new_with_transport(MockServer::URL, Some("bot/1.0".into()), caps, transport)
+        .await
+        .unwrap();
+    let req = &mock.requests()[0];
+    let always = &req.body["capabilities"]["alwaysMatch"];
+    assert_eq!(always["pageLoadStrategy"], "eager");
+    assert_eq!(always["moz:firefoxOptions"]["args"][0], "-headless");
+    let ua = req.headers.iter().find(|(k, _)| k == "user-agent");
+    assert_eq!(ua.map(|(_, v)| v.as_str()), Some("bot/1.0"));
+}
+
+#[tokio::test]
+async fn session_not_created() {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    // the default window route is only reached once a session exists
+    mock.on(
+        Method::GET,
+        "window",
+        Reply::Error(ErrorStatus::SessionNotCreated, "no browser".into()),
+    );
+    match mock.driver().await {
+        Err(Error(ErrorKind::WebDriver(e), _)) => {
+            assert_eq!(e.error, ErrorStatus::SessionNotCreated)
+        }
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("session created"),
+    }
+}
+
+#[tokio::test]
+async fn quit_deletes_session() {
+    for flavor in Flavor::ALL {
+        let mock = MockServer::new(flavor);
+        let driver = mock.driver().await.unwrap();
+        let clone = driver.clone();
+        driver.quit().await.unwrap();
+        // still referenced by the clone
+        assert!(mock.requests_to(Method::DELETE, "").is_empty());
+        clone.quit().await.unwrap();
+        assert_eq!(mock.requests_to(Method::DELETE, "").len(), 1);
+    }
+}
+
+#[tokio::test]
+async fn drop_deletes_session() {
+    let mock = MockServer::new(Flavor::Chromedriver);
+    drop(mock.driver().await.unwrap());
+    // the delete is sent in the background
+    tokio::task::yield_now().await;
+    assert_eq!(mock.requests_to(Method::DELETE, "").len(), 1);
+}
+
+/// A transport for another runtime, which runs spawned tasks when told
+struct Spawner {
+    mock: MockServer,
+    tasks: Mutex<Vec<BoxFuture<'static, ()>>>,
+}
+
+impl Transport for Spawner {
+    fn send(&self, request: Request) -> BoxFuture<'static, Result<Response>> {
+        self.mock.send(request)
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.mock.sleep(duration)
+    }
+
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        self.tasks.lock().unwrap().push(task)
+    }
+}
+
+#[test]
+fn drop_without_tokio() {
+    let mock = MockServer::new(Flavor::Geckodriver);
+    let spawner = Arc::new(Spawner {
+        mock: mock.clone(),
+        tasks: Mutex::new(Vec::new()),
+    });
+    let url = MockServer::URL;
+    let caps = Capabilities::new();
+    let driver = Driver::new_with_transport(url, None, caps, spawner.clone());
+    drop(futures::executor::block_on(driver).unwrap());
+    let tasks = std::mem::take(&mut *spawner.tasks.lock().unwrap());
+    assert_eq!(tasks.len(), 1);
+    futures::executor::block_on(futures::future::join_all(tasks));
+    assert_eq!(mock.requests_to(Method::DELETE, "").len(), 1);
+}
+
+#[tokio::test]
+async fn status() {
+    for flavor in Flavor::ALL {
+        let mock = MockServer::new(flavor);
+        let driver = mock.driver().await.unwrap();
+        let status = driver.status().await.unwrap();
+        assert!(status.ready);
+        let build = json!({"build": {"version": "2.1"}});
+        mock.on(Method::GET, "status", Reply::Value(build));
+        // servers that don't report readiness are assumed ready
+        assert!(driver.status().await.unwrap().ready);
+    }
+}